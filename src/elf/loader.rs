@@ -0,0 +1,202 @@
+use alloc::{sync::Arc, vec::Vec};
+
+use x86_64::{structures::paging::PageTableFlags, VirtAddr};
+
+use super::{Elf, ElfError, PT_LOAD};
+use crate::{
+    memory::{
+        address_space::{AddressSpace, USER_END, USER_START},
+        vm::VmError,
+    },
+    syscall,
+    thread::{self, JoinHandle},
+};
+
+/// Exclusive end of the user stack
+pub const USER_STACK_TOP: u64 = USER_END;
+pub const USER_STACK_SIZE: u64 = 16 * 4096;
+
+const PAGE_SIZE: u64 = 4096;
+
+// auxiliary vector entry types
+const AT_NULL: u64 = 0;
+const AT_PHDR: u64 = 3;
+const AT_PHENT: u64 = 4;
+const AT_PHNUM: u64 = 5;
+const AT_PAGESZ: u64 = 6;
+const AT_ENTRY: u64 = 9;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Elf(ElfError),
+    Vm(VmError),
+    /// A segment or the entry point is outside the range user programs can be mapped in
+    OutsideUserSpace,
+    /// The arguments and environment do not fit on the user stack
+    ArgumentsTooLong,
+}
+
+impl From<ElfError> for LoadError {
+    fn from(err: ElfError) -> Self {
+        Self::Elf(err)
+    }
+}
+
+impl From<VmError> for LoadError {
+    fn from(err: VmError) -> Self {
+        Self::Vm(err)
+    }
+}
+
+/// Map every `PT_LOAD` segment of `elf` into `space` with page flags matching its permissions.
+///
+/// Returns the entry point.
+///
+/// # Errors
+///
+/// Will error if a segment is outside user space or mapping it fails.
+pub fn load(elf: &Elf, space: &mut AddressSpace) -> Result<VirtAddr, LoadError> {
+    let entry = elf.entry();
+    if !(USER_START..USER_END).contains(&entry) {
+        return Err(LoadError::OutsideUserSpace);
+    }
+
+    for header in elf.program_headers().filter(|h| h.kind == PT_LOAD) {
+        if header.mem_size == 0 {
+            continue;
+        }
+
+        let end = header.vaddr + header.mem_size;
+        if header.vaddr < USER_START || end > USER_END {
+            return Err(LoadError::OutsideUserSpace);
+        }
+
+        let mut flags = PageTableFlags::empty();
+        if header.is_writable() {
+            flags |= PageTableFlags::WRITABLE;
+        }
+        if !header.is_executable() {
+            flags |= PageTableFlags::NO_EXECUTE;
+        }
+
+        let start = VirtAddr::new(header.vaddr).align_down(PAGE_SIZE);
+        let end = VirtAddr::new(end).align_up(PAGE_SIZE);
+        let mut page = start;
+        while page < end {
+            map_page(space, page, flags)?;
+            page += PAGE_SIZE;
+        }
+
+        // fresh pages are zeroed, so the rest of the segment (its bss) already is
+        space.write(VirtAddr::new(header.vaddr), elf.segment_data(&header))?;
+    }
+
+    Ok(VirtAddr::new(entry))
+}
+
+/// Map `page` with `flags`, or if an earlier segment already mapped it, allow what either segment allows
+fn map_page(
+    space: &mut AddressSpace,
+    page: VirtAddr,
+    flags: PageTableFlags,
+) -> Result<(), VmError> {
+    let end = page + PAGE_SIZE;
+
+    match space.translate(page) {
+        None => space.map(page, end, flags),
+        Some((_, existing)) => {
+            let no_execute = existing & flags & PageTableFlags::NO_EXECUTE;
+            let merged = ((existing | flags) - PageTableFlags::NO_EXECUTE) | no_execute;
+            space.set_flags(page, end, merged)
+        }
+    }
+}
+
+/// Map the user stack and lay out `argv`, `envp` and the auxiliary vector on it like the System V abi expects.
+///
+/// Returns the initial stack pointer, which points at `argc`.
+///
+/// # Errors
+///
+/// Will error if mapping the stack fails or everything does not fit on it.
+pub fn setup_stack(
+    space: &mut AddressSpace,
+    elf: &Elf,
+    argv: &[&str],
+    envp: &[&str],
+) -> Result<VirtAddr, LoadError> {
+    let top = VirtAddr::new(USER_STACK_TOP);
+    space.map(
+        top - USER_STACK_SIZE,
+        top,
+        PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE,
+    )?;
+
+    // the strings go at the very top, in order, each followed by a nul
+    let strings_size: usize = argv.iter().chain(envp).map(|s| s.len() + 1).sum();
+    let strings_start = USER_STACK_TOP
+        .checked_sub(strings_size as u64)
+        .ok_or(LoadError::ArgumentsTooLong)?;
+
+    let mut strings = Vec::with_capacity(strings_size);
+    let mut pointers = Vec::with_capacity(argv.len() + envp.len());
+    for s in argv.iter().chain(envp) {
+        pointers.push(strings_start + strings.len() as u64);
+        strings.extend_from_slice(s.as_bytes());
+        strings.push(0);
+    }
+    let (argv_pointers, envp_pointers) = pointers.split_at(argv.len());
+
+    let auxv = [
+        (AT_PHDR, elf.program_headers_addr().unwrap_or(0)),
+        (AT_PHENT, super::PROGRAM_HEADER_SIZE as u64),
+        (AT_PHNUM, elf.program_header_count() as u64),
+        (AT_PAGESZ, PAGE_SIZE),
+        (AT_ENTRY, elf.entry()),
+        (AT_NULL, 0),
+    ];
+
+    // argc, argv, null, envp, null, auxv
+    let mut table = Vec::with_capacity(1 + pointers.len() + 2 + auxv.len() * 2);
+    table.push(argv.len() as u64);
+    table.extend_from_slice(argv_pointers);
+    table.push(0);
+    table.extend_from_slice(envp_pointers);
+    table.push(0);
+    for (kind, value) in auxv {
+        table.push(kind);
+        table.push(value);
+    }
+
+    let table_size = (table.len() * 8) as u64;
+    let rsp = VirtAddr::new(strings_start)
+        .as_u64()
+        .checked_sub(table_size)
+        .map(|rsp| VirtAddr::new(rsp).align_down(16u64))
+        .filter(|&rsp| rsp > top - USER_STACK_SIZE)
+        .ok_or(LoadError::ArgumentsTooLong)?;
+
+    let table_bytes: Vec<u8> = table.iter().flat_map(|word| word.to_le_bytes()).collect();
+    space.write(rsp, &table_bytes)?;
+    space.write(VirtAddr::new(strings_start), &strings)?;
+
+    Ok(rsp)
+}
+
+/// Load the executable in `data` into a new address space and start running it in user mode on a new thread.
+///
+/// # Errors
+///
+/// Will error if the executable is invalid, or loading it or spawning the thread fails.
+pub fn exec(data: &[u8], argv: &[&str], envp: &[&str]) -> Result<JoinHandle<()>, LoadError> {
+    let elf = Elf::parse(data)?;
+    let mut space = AddressSpace::new()?;
+
+    let entry = load(&elf, &mut space)?;
+    let stack = setup_stack(&mut space, &elf, argv, envp)?;
+
+    let handle = thread::spawn_thread_in(Arc::new(space), move || unsafe {
+        syscall::enter_user_mode(entry, stack)
+    })?;
+    Ok(handle)
+}
@@ -0,0 +1,226 @@
+mod loader;
+
+pub use loader::{exec, load, setup_stack, LoadError, USER_STACK_SIZE, USER_STACK_TOP};
+
+const MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const CLASS_64: u8 = 2;
+const LITTLE_ENDIAN: u8 = 1;
+const CURRENT_VERSION: u8 = 1;
+/// `ET_EXEC`, position dependent executables
+const TYPE_EXECUTABLE: u16 = 2;
+const MACHINE_X86_64: u16 = 0x3e;
+
+const HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+
+pub const PT_LOAD: u32 = 1;
+pub const PT_PHDR: u32 = 6;
+
+pub const PF_X: u32 = 1;
+pub const PF_W: u32 = 2;
+pub const PF_R: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElfError {
+    /// The file ends before a header does
+    TooShort,
+    BadMagic,
+    Not64Bit,
+    NotLittleEndian,
+    UnsupportedVersion,
+    /// Only statically linked, position dependent executables can be loaded
+    NotExecutable,
+    NotX86_64,
+    /// The program header table has an unexpected entry size or does not fit in the file
+    BadProgramHeaders,
+    /// A segment's contents do not fit in the file, or it is smaller in memory than in the file
+    BadSegment,
+}
+
+/// A validated ELF64 executable
+#[derive(Debug, Clone, Copy)]
+pub struct Elf<'a> {
+    data: &'a [u8],
+    entry: u64,
+    ph_offset: usize,
+    ph_count: usize,
+}
+
+impl<'a> Elf<'a> {
+    /// Validate the headers of the executable in `data`
+    ///
+    /// # Errors
+    ///
+    /// See [`ElfError`]
+    pub fn parse(data: &'a [u8]) -> Result<Self, ElfError> {
+        if data.len() < HEADER_SIZE {
+            return Err(ElfError::TooShort);
+        }
+        if data[0..4] != MAGIC {
+            return Err(ElfError::BadMagic);
+        }
+        if data[4] != CLASS_64 {
+            return Err(ElfError::Not64Bit);
+        }
+        if data[5] != LITTLE_ENDIAN {
+            return Err(ElfError::NotLittleEndian);
+        }
+        if data[6] != CURRENT_VERSION {
+            return Err(ElfError::UnsupportedVersion);
+        }
+        if read_u16(data, 16) != Some(TYPE_EXECUTABLE) {
+            return Err(ElfError::NotExecutable);
+        }
+        if read_u16(data, 18) != Some(MACHINE_X86_64) {
+            return Err(ElfError::NotX86_64);
+        }
+
+        let entry = read_u64(data, 24).ok_or(ElfError::TooShort)?;
+        let ph_offset = read_u64(data, 32).ok_or(ElfError::TooShort)?;
+        let ph_size = read_u16(data, 54).ok_or(ElfError::TooShort)?;
+        let ph_count = read_u16(data, 56).ok_or(ElfError::TooShort)?;
+
+        if usize::from(ph_size) != PROGRAM_HEADER_SIZE {
+            return Err(ElfError::BadProgramHeaders);
+        }
+        let ph_offset = usize::try_from(ph_offset).map_err(|_| ElfError::BadProgramHeaders)?;
+        let ph_count = usize::from(ph_count);
+        let table_end = ph_offset
+            .checked_add(ph_count * PROGRAM_HEADER_SIZE)
+            .ok_or(ElfError::BadProgramHeaders)?;
+        if table_end > data.len() {
+            return Err(ElfError::BadProgramHeaders);
+        }
+
+        let elf = Self {
+            data,
+            entry,
+            ph_offset,
+            ph_count,
+        };
+        for header in elf.program_headers() {
+            header.validate(data.len())?;
+        }
+
+        Ok(elf)
+    }
+
+    /// The address execution starts at
+    #[must_use]
+    pub fn entry(&self) -> u64 {
+        self.entry
+    }
+
+    pub fn program_headers(&self) -> impl Iterator<Item = ProgramHeader> + 'a {
+        let data = self.data;
+        let ph_offset = self.ph_offset;
+        (0..self.ph_count)
+            .map(move |i| ProgramHeader::read(data, ph_offset + i * PROGRAM_HEADER_SIZE))
+    }
+
+    /// The file contents of `header`'s segment
+    #[must_use]
+    pub fn segment_data(&self, header: &ProgramHeader) -> &'a [u8] {
+        // validated in `parse`, so this cannot be out of bounds
+        let start = usize::try_from(header.offset).unwrap_or(0);
+        let len = usize::try_from(header.file_size).unwrap_or(0);
+        &self.data[start..start + len]
+    }
+
+    /// Where the program header table is in memory once loaded, for the `AT_PHDR` auxiliary vector entry
+    #[must_use]
+    pub fn program_headers_addr(&self) -> Option<u64> {
+        if let Some(phdr) = self.program_headers().find(|h| h.kind == PT_PHDR) {
+            return Some(phdr.vaddr);
+        }
+
+        // otherwise the table is loaded with whichever segment contains it
+        let offset = self.ph_offset as u64;
+        self.program_headers()
+            .filter(|h| h.kind == PT_LOAD)
+            .find(|h| h.offset <= offset && offset < h.offset + h.file_size)
+            .map(|h| h.vaddr + (offset - h.offset))
+    }
+
+    #[must_use]
+    pub fn program_header_count(&self) -> usize {
+        self.ph_count
+    }
+}
+
+/// An entry of the program header table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgramHeader {
+    /// `p_type`, like [`PT_LOAD`]
+    pub kind: u32,
+    /// `p_flags`, a combination of [`PF_R`], [`PF_W`] and [`PF_X`]
+    pub flags: u32,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub file_size: u64,
+    pub mem_size: u64,
+    pub align: u64,
+}
+
+impl ProgramHeader {
+    fn read(data: &[u8], offset: usize) -> Self {
+        // the table was checked to fit in `data`
+        let field = |at| read_u64(data, offset + at).unwrap_or(0);
+        Self {
+            kind: read_u32(data, offset).unwrap_or(0),
+            flags: read_u32(data, offset + 4).unwrap_or(0),
+            offset: field(8),
+            vaddr: field(16),
+            file_size: field(32),
+            mem_size: field(40),
+            align: field(48),
+        }
+    }
+
+    fn validate(&self, file_len: usize) -> Result<(), ElfError> {
+        if self.kind != PT_LOAD {
+            return Ok(());
+        }
+
+        let in_file = self
+            .offset
+            .checked_add(self.file_size)
+            .is_some_and(|end| end <= file_len as u64);
+        let in_memory = self.vaddr.checked_add(self.mem_size).is_some();
+        let aligned = self.align <= 1 || self.vaddr % self.align == self.offset % self.align;
+
+        if in_file && in_memory && aligned && self.file_size <= self.mem_size {
+            Ok(())
+        } else {
+            Err(ElfError::BadSegment)
+        }
+    }
+
+    #[must_use]
+    pub fn is_writable(&self) -> bool {
+        self.flags & PF_W != 0
+    }
+
+    #[must_use]
+    pub fn is_executable(&self) -> bool {
+        self.flags & PF_X != 0
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
@@ -1,9 +1,13 @@
-use core::ptr::addr_of;
+use core::{
+    cell::UnsafeCell,
+    ptr::addr_of,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 use conquer_once::spin::Lazy;
 use x86_64::{
-    instructions::tables,
-    registers::segmentation::{Segment, CS},
+    instructions::{interrupts, tables},
+    registers::segmentation::{Segment, CS, DS, ES, SS},
     structures::{
         gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector},
         tss::TaskStateSegment,
@@ -11,42 +15,122 @@ use x86_64::{
     VirtAddr,
 };
 
+use crate::memory::{
+    stack::{KernelStack, DEFAULT_STACK_SIZE},
+    vm::VmError,
+};
+
 pub const DOUBLE_FAULT_IST_INDEX: usize = 0;
 
+/// Amount of interrupt stack table entries in use
+const IST_STACKS: usize = 1;
+
 static GDT: Lazy<(GlobalDescriptorTable, Selectors)> = Lazy::new(|| {
     let mut gdt = GlobalDescriptorTable::new();
     let code = gdt.append(Descriptor::kernel_code_segment());
-    let tss = gdt.append(Descriptor::tss_segment(&TSS));
+    let data = gdt.append(Descriptor::kernel_data_segment());
+    // `sysret` expects the user data segment right before the user code segment
+    let user_data = gdt.append(Descriptor::user_data_segment());
+    let user_code = gdt.append(Descriptor::user_code_segment());
+    // safety: the TSS is a static, so it lives forever
+    let tss = gdt.append(unsafe { Descriptor::tss_segment_unchecked(TSS.0.get()) });
 
-    (gdt, Selectors { code, tss })
+    (
+        gdt,
+        Selectors {
+            code,
+            data,
+            user_code,
+            user_data,
+            tss,
+        },
+    )
 });
 
-static TSS: Lazy<TaskStateSegment> = Lazy::new(|| {
-    let mut tss = TaskStateSegment::new();
-    tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX] = {
-        const STACK_SIZE: usize = 4096 * 5;
-        static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+/// The TSS is only read by the cpu once loaded, so its stacks can still be replaced.
+struct Tss(UnsafeCell<TaskStateSegment>);
 
-        #[allow(unused_unsafe)]
-        let stack_start = VirtAddr::from_ptr(unsafe { addr_of!(STACK) });
+// safety: the TSS is only written to with interrupts disabled, see `set_ist`
+unsafe impl Sync for Tss {}
 
-        // stack end
-        stack_start + STACK_SIZE as u64
-    };
+static TSS: Tss = Tss(UnsafeCell::new(TaskStateSegment::new()));
 
-    tss
-});
+/// Stacks used until [`init_stacks`] is called, since they cannot come from [`crate::memory::vm`] that early.
+static mut EARLY_STACKS: [[u8; EARLY_STACK_SIZE]; IST_STACKS] = [[0; EARLY_STACK_SIZE]; IST_STACKS];
+const EARLY_STACK_SIZE: usize = 4096 * 5;
 
-struct Selectors {
-    code: SegmentSelector,
+/// The stack the cpu switches to when entering the kernel from user mode, since the TSS cannot be read back.
+/// Also used by the `syscall` entry, which does not switch stacks on its own.
+pub(crate) static KERNEL_STACK: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy)]
+pub struct Selectors {
+    pub code: SegmentSelector,
+    pub data: SegmentSelector,
+    pub user_code: SegmentSelector,
+    pub user_data: SegmentSelector,
     tss: SegmentSelector,
 }
 
+/// Get the segment selectors of the GDT
+#[must_use]
+pub fn selectors() -> Selectors {
+    GDT.1
+}
+
+/// Set the interrupt stack table entry at `index` to the stack ending at `top`
+fn set_ist(index: usize, top: VirtAddr) {
+    interrupts::without_interrupts(|| unsafe {
+        (*TSS.0.get()).interrupt_stack_table[index] = top;
+    });
+}
+
 pub fn init() {
+    #[allow(unused_unsafe)]
+    let stacks_start = VirtAddr::from_ptr(unsafe { addr_of!(EARLY_STACKS) });
+    for index in 0..IST_STACKS {
+        // stack end
+        set_ist(
+            index,
+            stacks_start + ((index + 1) * EARLY_STACK_SIZE) as u64,
+        );
+    }
+
     GDT.0.load();
 
     unsafe {
         CS::set_reg(GDT.1.code);
+        SS::set_reg(GDT.1.data);
+        DS::set_reg(GDT.1.data);
+        ES::set_reg(GDT.1.data);
         tables::load_tss(GDT.1.tss);
     }
 }
+
+/// Set the stack the cpu switches to when entering the kernel from user mode, through an interrupt or `syscall`.
+///
+/// Every thread that can run user code needs its own, so this is called on every context switch.
+pub fn set_kernel_stack(top: VirtAddr) {
+    interrupts::without_interrupts(|| unsafe {
+        (*TSS.0.get()).privilege_stack_table[0] = top;
+    });
+    KERNEL_STACK.store(top.as_u64(), Ordering::Relaxed);
+}
+
+/// Replace the interrupt stacks with guard-paged [`KernelStack`]s, and allocate the kernel stack used when entering
+/// the kernel from user mode before any thread sets its own. Must be called after [`crate::memory::init`].
+///
+/// # Errors
+///
+/// Will error if allocating a stack fails. The stacks replaced before that stay in use.
+pub fn init_stacks() -> Result<(), VmError> {
+    for index in 0..IST_STACKS {
+        let stack = KernelStack::new(DEFAULT_STACK_SIZE)?;
+        set_ist(index, stack.leak());
+    }
+
+    let stack = KernelStack::new(DEFAULT_STACK_SIZE)?;
+    set_kernel_stack(stack.leak());
+
+    Ok(())
+}
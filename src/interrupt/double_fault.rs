@@ -1,6 +1,22 @@
-use x86_64::structures::idt::InterruptStackFrame;
+use x86_64::{registers::control::Cr2, structures::idt::InterruptStackFrame, VirtAddr};
+
+use super::page_fault;
 
 #[allow(clippy::panic, reason = "double faults are unrecoverable")]
 pub extern "x86-interrupt" fn handler(frame: InterruptStackFrame, error_code: u64) -> ! {
-    panic!("NOOOOO DOUBLE FAULT {error_code} (panicking): {frame:#?}");
+    // overflowing a stack page faults, and pushing that fault's frame below the stack pointer faults again,
+    // so the stack pointer is in a guard page or right above one
+    let stack_pointer = frame.stack_pointer;
+    let pushed_to = VirtAddr::try_new(stack_pointer.as_u64().wrapping_sub(8)).ok();
+    if page_fault::in_guard_region(stack_pointer)
+        || pushed_to.is_some_and(page_fault::in_guard_region)
+    {
+        page_fault::stack_overflow(stack_pointer, &frame);
+    }
+
+    // CR2 is only set by page faults, so it may be from one that was handled long before this
+    panic!(
+        "NOOOOO DOUBLE FAULT {error_code} (panicking), last page fault at {:?} (may be stale): {frame:#?}",
+        Cr2::read()
+    );
 }
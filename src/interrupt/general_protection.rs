@@ -0,0 +1,21 @@
+use x86_64::{structures::idt::InterruptStackFrame, PrivilegeLevel};
+
+use crate::{hlt_loop, println, thread, vga};
+
+pub extern "x86-interrupt" fn handler(stack_frame: InterruptStackFrame, error_code: u64) {
+    // user code is not trusted, so it only takes down its own thread
+    if stack_frame.code_segment.rpl() == PrivilegeLevel::Ring3 {
+        log::error!(
+            "user general protection fault at {:?}, killing thread",
+            stack_frame.instruction_pointer
+        );
+        thread::exit();
+    }
+
+    vga::switch_console(0);
+    println!("EXCEPTION: GENERAL PROTECTION FAULT");
+    println!("error code: {error_code}");
+    println!("{stack_frame:#?}");
+
+    hlt_loop();
+}
@@ -3,8 +3,11 @@ use x86_64::{instructions::port::Port, structures::idt::InterruptStackFrame};
 use crate::interrupt::{notify_end_of_interrupt, InterruptIndex};
 
 pub extern "x86-interrupt" fn handler(_frame: InterruptStackFrame) {
-    let scancode: u8 = unsafe { Port::new(0x60).read() };
-    crate::task::keyboard::add_scancode(scancode);
+    let byte: u8 = unsafe { Port::new(0x60).read() };
+    // responses to commands sent to the keyboard come through here too
+    if !crate::ps2::keyboard::handle_byte(byte) {
+        crate::task::keyboard::add_scancode(byte);
+    }
 
     unsafe {
         notify_end_of_interrupt(InterruptIndex::Keyboard as u8);
@@ -1,7 +1,10 @@
 mod breakpoint;
 mod double_fault;
+mod general_protection;
 mod keyboard;
+mod mouse;
 mod page_fault;
+mod serial;
 mod timer;
 
 use conquer_once::spin::Lazy;
@@ -17,12 +20,18 @@ pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 pub static PICS: Mutex<ChainedPics> =
     Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum InterruptIndex {
     Timer = PIC_1_OFFSET,
     // timer + 1
     Keyboard,
+    /// IRQ 3, COM2 and COM4
+    Com2 = PIC_1_OFFSET + 3,
+    /// IRQ 4, COM1 and COM3
+    Com1,
+    /// IRQ 12, on the second PIC
+    Mouse = PIC_2_OFFSET + 4,
 }
 
 pub fn init_idt() {
@@ -41,15 +50,39 @@ static IDT: Lazy<InterruptDescriptorTable> = Lazy::new(|| {
             .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX as u16);
     }
 
+    // page faults stay on the faulting stack, since handling them can fault again.
+    // a page fault from overflowing a stack can not push its frame, so it becomes a double fault instead
     idt.page_fault.set_handler_fn(page_fault::handler);
     idt.breakpoint.set_handler_fn(breakpoint::handler);
+    idt.general_protection_fault
+        .set_handler_fn(general_protection::handler);
 
     idt[InterruptIndex::Timer as u8].set_handler_fn(timer::handler);
     idt[InterruptIndex::Keyboard as u8].set_handler_fn(keyboard::handler);
+    idt[InterruptIndex::Com2 as u8].set_handler_fn(serial::com2_handler);
+    idt[InterruptIndex::Com1 as u8].set_handler_fn(serial::com1_handler);
+    idt[InterruptIndex::Mouse as u8].set_handler_fn(mouse::handler);
 
     idt
 });
 
+/// Unmask `interrupt` on the PICs, and the line the second PIC is chained through if it is on that one
+pub fn enable_irq(interrupt: InterruptIndex) {
+    let irq = interrupt as u8 - PIC_1_OFFSET;
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut pics = PICS.lock();
+        let [mut first, mut second] = unsafe { pics.read_masks() };
+        if irq < 8 {
+            first &= !(1 << irq);
+        } else {
+            second &= !(1 << (irq - 8));
+            first &= !(1 << 2);
+        }
+        unsafe { pics.write_masks(first, second) };
+    });
+}
+
 #[inline]
 unsafe fn notify_end_of_interrupt(interrupt_id: u8) {
     PICS.lock().notify_end_of_interrupt(interrupt_id);
@@ -0,0 +1,14 @@
+use x86_64::{instructions::port::Port, structures::idt::InterruptStackFrame};
+
+use crate::interrupt::{notify_end_of_interrupt, InterruptIndex};
+
+pub extern "x86-interrupt" fn handler(_frame: InterruptStackFrame) {
+    let byte: u8 = unsafe { Port::new(0x60).read() };
+    if let Some(event) = crate::ps2::mouse::handle_byte(byte) {
+        crate::task::mouse::add_event(event);
+    }
+
+    unsafe {
+        notify_end_of_interrupt(InterruptIndex::Mouse as u8);
+    }
+}
@@ -1,19 +1,60 @@
 use x86_64::{
     registers::control::Cr2,
     structures::idt::{InterruptStackFrame, PageFaultErrorCode},
+    VirtAddr,
 };
 
-use crate::{hlt_loop, println};
+use crate::{
+    hlt_loop,
+    memory::vm::{self, RegionKind},
+    println, thread, vga,
+};
 
 pub extern "x86-interrupt" fn handler(
     stack_frame: InterruptStackFrame,
     error_code: PageFaultErrorCode,
 ) {
-    println!("EXCEPTION: PAGE FAULT");
     // CR2: control register 2 - contains address which triggered the page fault.
-    println!("acessed address: {:?}", Cr2::read());
+    let addr = Cr2::read();
+
+    if let Ok(addr) = addr {
+        if vm::handle_page_fault(addr, error_code) {
+            return;
+        }
+
+        if error_code.contains(PageFaultErrorCode::USER_MODE) {
+            log::error!("user page fault at {addr:?} ({error_code:?}), killing thread");
+            thread::exit();
+        }
+
+        if is_stack_overflow(addr, stack_frame.stack_pointer) {
+            stack_overflow(addr, &stack_frame);
+        }
+    }
+
+    vga::switch_console(0);
+    println!("EXCEPTION: PAGE FAULT");
+    println!("acessed address: {addr:?}");
     println!("error code: {error_code:?}");
     println!("{stack_frame:#?}");
 
     hlt_loop();
 }
+
+/// Whether `addr` is in the guard page of a kernel stack, or in the page just below the stack pointer
+/// (for stacks that did not come from [`vm`], like the one the bootloader gave us).
+fn is_stack_overflow(addr: VirtAddr, stack_pointer: VirtAddr) -> bool {
+    let below_stack = addr <= stack_pointer && stack_pointer - addr < 4096;
+
+    in_guard_region(addr) || below_stack
+}
+
+/// Whether `addr` is in the guard page of a stack allocated through [`vm`]
+pub(super) fn in_guard_region(addr: VirtAddr) -> bool {
+    vm::try_region_at(addr).is_some_and(|r| r.kind == RegionKind::Guard)
+}
+
+#[allow(clippy::panic, reason = "stack overflows are unrecoverable")]
+pub(super) fn stack_overflow(addr: VirtAddr, stack_frame: &InterruptStackFrame) -> ! {
+    panic!("EXCEPTION: STACK OVERFLOW at {addr:?} (panicking): {stack_frame:#?}");
+}
@@ -0,0 +1,19 @@
+use x86_64::structures::idt::InterruptStackFrame;
+
+use crate::interrupt::{notify_end_of_interrupt, InterruptIndex};
+
+pub extern "x86-interrupt" fn com1_handler(_frame: InterruptStackFrame) {
+    handle(InterruptIndex::Com1);
+}
+
+pub extern "x86-interrupt" fn com2_handler(_frame: InterruptStackFrame) {
+    handle(InterruptIndex::Com2);
+}
+
+fn handle(interrupt: InterruptIndex) {
+    crate::serial::handle_interrupt(interrupt);
+
+    unsafe {
+        notify_end_of_interrupt(interrupt as u8);
+    }
+}
@@ -3,9 +3,13 @@ use x86_64::structures::idt::InterruptStackFrame;
 use crate::interrupt::{notify_end_of_interrupt, InterruptIndex};
 
 pub extern "x86-interrupt" fn handler(_frame: InterruptStackFrame) {
-    // crate::print!(".");
+    crate::time::tick();
+    crate::task::timer::wake_expired();
 
     unsafe {
         notify_end_of_interrupt(InterruptIndex::Timer as u8);
     }
+
+    // after the eoi, since the next thread may not return here for a while
+    crate::thread::scheduler::preempt();
 }
@@ -13,28 +13,51 @@ pub mod qemu;
 pub mod serial;
 pub mod vga;
 
+pub mod shell;
+
 pub mod gdt;
 pub mod interrupt;
+pub mod ps2;
 
 pub mod memory;
 
+pub mod elf;
+pub mod syscall;
+
 pub mod task;
+pub mod thread;
+
+pub mod time;
 
 use core::{any, panic::PanicInfo};
 
-use log::trace;
-use x86_64::instructions;
+use log::{trace, warn};
+use x86_64::{
+    instructions::{self, port::Port},
+    structures::DescriptorTablePointer,
+    VirtAddr,
+};
 
 /// initialize
 /// - gdt
+/// - syscalls
 /// - idt
 /// - PICs
+/// - PS/2 controller
+/// - COM1 receive interrupts
+/// - PIT
 /// - interrupts
 pub fn init() {
     trace!("first init");
     gdt::init();
+    syscall::init();
     interrupt::init_idt();
     unsafe { interrupt::PICS.lock().initialize() };
+    if let Err(err) = ps2::init() {
+        warn!("failed to initialise ps/2 controller: {err:?}");
+    }
+    serial::init();
+    time::init();
     x86_64::instructions::interrupts::enable();
 }
 
@@ -45,6 +68,35 @@ pub fn hlt_loop() -> ! {
     }
 }
 
+/// Reset the machine by pulsing the reset line through the keyboard controller.
+///
+/// If that does not work, trigger a triple fault by loading an empty IDT and raising an exception.
+pub fn reboot() -> ! {
+    trace!("rebooting");
+    instructions::interrupts::disable();
+
+    let mut controller = Port::<u8>::new(0x64);
+    // wait a bit for the controller's input buffer to empty, then give it time to reset us
+    for _ in 0..10_000 {
+        if unsafe { controller.read() } & 0b10 == 0 {
+            break;
+        }
+    }
+    unsafe { controller.write(0xfe) };
+    for _ in 0..1_000_000 {
+        core::hint::spin_loop();
+    }
+
+    let empty = DescriptorTablePointer {
+        limit: 0,
+        base: VirtAddr::zero(),
+    };
+    unsafe { instructions::tables::lidt(&empty) };
+    instructions::interrupts::int3();
+
+    hlt_loop();
+}
+
 pub trait Testable {
     fn run(&self);
 }
@@ -69,8 +121,11 @@ pub fn runner(tests: &[&dyn Testable]) {
 bootloader::entry_point!(test_kernel_main);
 
 #[cfg(test)]
-fn test_kernel_main(_boot_info: &'static bootloader::BootInfo) -> ! {
-    interrupt::init_idt();
+fn test_kernel_main(boot_info: &'static bootloader::BootInfo) -> ! {
+    init();
+    // some unit tests allocate
+    unsafe { memory::init(boot_info) };
+    memory::allocator::init_heap().expect("heap init failed");
     test_main();
     hlt_loop();
 }
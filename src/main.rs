@@ -11,17 +11,23 @@ extern crate alloc;
 use bootloader::{entry_point, BootInfo};
 use core::panic::PanicInfo;
 use osos::{
-    memory::{allocator, paging},
-    print, println, serial_println,
-    task::{executor::Executor, keyboard, Task},
+    gdt,
+    memory::{self, allocator},
+    print, println,
+    serial::ComPort,
+    serial_println, shell,
+    task::{executor::Executor, keyboard, timer::sleep, Task},
+    thread,
+    time::Duration,
     vga::{self, init_logger},
 };
-use x86_64::VirtAddr;
 
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     // cannot use log crate here for some reason.
+    // nothing would handle alt+f1 anymore, so show the console this is printed to
+    vga::switch_console(0);
     println!("\n\nPANIC: {info}");
     serial_println!("{info}");
     osos::hlt_loop();
@@ -51,38 +57,37 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     #[cfg(test)]
     test_main();
 
-    let phys_offset = VirtAddr::new(boot_info.physical_memory_offset);
-    let mut mapper = unsafe { paging::init_offset_table(phys_offset) };
-    let mut frame_allocator = unsafe { paging::BootInfoFrameAllocator::new(&boot_info.memory_map) };
+    unsafe { memory::init(boot_info) };
+    gdt::init_stacks().expect("interrupt stack init failed");
+    allocator::init_heap().expect("heap init failed");
+    vga::set_scrollback(vga::DEFAULT_SCROLLBACK);
+    thread::init().expect("scheduler init failed");
 
-    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap init failed");
+    let mut executor = Executor::new();
+    executor.spawn(Task::new(heap_demo()));
+    executor.spawn(Task::new(keyboard::process_keys()));
+    executor.spawn(Task::new(shell::run()));
+    // com1 is stdio when running headless, so the shell takes it and the serial log moves to com2, if there is one
+    executor.spawn(Task::new(shell::run_serial(ComPort::Com1)));
+    executor.run();
+}
 
-    // test heap
-    {
-        let mut suse = alloc::vec![1, 2, 3];
-        print!("{suse:?}, ");
+async fn heap_demo() {
+    let mut suse = alloc::vec![1, 2, 3];
+    print!("{suse:?}, ");
 
-        suse.push(10);
+    suse.push(10);
 
-        // sleep some
-        for _ in 0..2_000_000 {
-            x86_64::instructions::nop();
-        }
+    // sleep some
+    sleep(Duration::from_millis(250)).await;
 
-        print!("now {suse:?}, ");
+    print!("now {suse:?}, ");
 
-        suse.pop();
-        suse.pop();
+    suse.pop();
+    suse.pop();
 
-        for _ in 0..2_000_000 {
-            x86_64::instructions::nop();
-        }
-        println!("now {suse:?}!");
-    }
+    sleep(Duration::from_millis(250)).await;
+    println!("now {suse:?}!");
 
     log::error!("We are done!");
-
-    let mut executor = Executor::new();
-    executor.spawn(Task::new(keyboard::print_keypresses()));
-    executor.run();
 }
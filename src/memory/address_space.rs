@@ -0,0 +1,370 @@
+use core::ptr;
+
+use x86_64::{
+    registers::control::Cr3,
+    structures::paging::{
+        mapper::{MapToError, UnmapError},
+        FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags,
+        PhysFrame, Size4KiB,
+    },
+    PhysAddr, VirtAddr,
+};
+
+use super::{frame::BitmapFrameAllocator, vm::VmError};
+use crate::memory;
+
+const PAGE_SIZE: u64 = 4096;
+
+/// Level 4 entries belonging to user space, everything else is shared with the kernel
+const USER_P4_ENTRIES: core::ops::Range<usize> = 64..128;
+
+/// Start of the range user regions can be mapped in
+pub const USER_START: u64 = 0x2000_0000_0000;
+/// Exclusive end of the range user regions can be mapped in
+pub const USER_END: u64 = 0x4000_0000_0000;
+
+/// A set of page tables for user programs.
+///
+/// The kernel's level 4 entries are copied when the address space is created, so kernel mappings made afterwards
+/// are only visible if they fall in level 4 entries that already existed (like the ones of the heap and
+/// [`super::vm::KERNEL_VM_START`]). Everything from [`USER_START`] to [`USER_END`] is private to the address space.
+#[derive(Debug)]
+pub struct AddressSpace {
+    p4: PhysFrame,
+}
+
+impl AddressSpace {
+    /// Create an address space with nothing mapped in user space
+    ///
+    /// # Errors
+    ///
+    /// Will error if there is no frame left for the level 4 table.
+    pub fn new() -> Result<Self, VmError> {
+        let p4 = memory::with_frame_allocator(FrameAllocator::allocate_frame)
+            .ok_or(VmError::Map(MapToError::FrameAllocationFailed))?;
+
+        let kernel_table = unsafe { &*table_ptr(memory::kernel_p4()) };
+        let table = unsafe { &mut *table_ptr(p4) };
+        table.zero();
+        for (index, entry) in kernel_table.iter().enumerate() {
+            if !USER_P4_ENTRIES.contains(&index) {
+                table[index] = entry.clone();
+            }
+        }
+
+        Ok(Self { p4 })
+    }
+
+    /// The frame of this address space's level 4 table
+    #[must_use]
+    pub fn p4_frame(&self) -> PhysFrame {
+        self.p4
+    }
+
+    /// Whether this address space's page tables are the ones in use
+    #[must_use]
+    pub fn is_active(&self) -> bool {
+        Cr3::read().0 == self.p4
+    }
+
+    /// Make this address space's page tables the ones in use
+    ///
+    /// # Safety
+    ///
+    /// Whatever the current code and stack use must be mapped the same in this address space, which is true
+    /// for kernel code.
+    pub unsafe fn activate(&self) {
+        activate_frame(self.p4);
+    }
+
+    /// Map fresh zeroed frames to every page from `start` to `end` (exclusive).
+    ///
+    /// `USER_ACCESSIBLE` and `PRESENT` are always added to `flags`. If mapping fails, the pages mapped by this call
+    /// are unmapped again.
+    ///
+    /// # Errors
+    ///
+    /// Will error if the range is not page aligned and inside user space, or mapping fails.
+    pub fn map(
+        &mut self,
+        start: VirtAddr,
+        end: VirtAddr,
+        flags: PageTableFlags,
+    ) -> Result<(), VmError> {
+        check_user_range(start, end)?;
+        let flags = flags | PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+        let mut mapper = unsafe { self.mapper() };
+
+        memory::with_frame_allocator(|frame_allocator| {
+            for (i, page) in pages(start, end).enumerate() {
+                if let Err(err) = map_zeroed(&mut mapper, frame_allocator, page, flags) {
+                    for page in pages(start, end).take(i) {
+                        unmap_page(&mut mapper, frame_allocator, page).ok();
+                    }
+                    return Err(VmError::Map(err));
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Unmap every mapped page from `start` to `end` (exclusive) and free their frames.
+    ///
+    /// # Errors
+    ///
+    /// Will error if the range is not page aligned and inside user space, or unmapping fails.
+    pub fn unmap(&mut self, start: VirtAddr, end: VirtAddr) -> Result<(), VmError> {
+        check_user_range(start, end)?;
+        let mut mapper = unsafe { self.mapper() };
+
+        memory::with_frame_allocator(|frame_allocator| {
+            for page in pages(start, end) {
+                match unmap_page(&mut mapper, frame_allocator, page) {
+                    Ok(()) | Err(UnmapError::PageNotMapped) => {}
+                    Err(err) => return Err(VmError::Unmap(err)),
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Get the physical address `addr` is mapped to and the flags of its page, if it is mapped.
+    ///
+    /// The flags only contain `USER_ACCESSIBLE` and `WRITABLE` if every level of the page tables allows it.
+    #[must_use]
+    pub fn translate(&self, addr: VirtAddr) -> Option<(PhysAddr, PageTableFlags)> {
+        translate_in(self.p4, addr)
+    }
+
+    /// Copy `data` into the memory at `addr`, which does not have to be writable to user code.
+    ///
+    /// Works whether or not the address space is active.
+    ///
+    /// # Errors
+    ///
+    /// Will error if any of the bytes are not mapped.
+    pub fn write(&mut self, addr: VirtAddr, data: &[u8]) -> Result<(), VmError> {
+        let mut written = 0;
+        while written < data.len() {
+            let addr = addr + written as u64;
+            let (phys, _) = self.translate(addr).ok_or(VmError::NotReserved)?;
+
+            // up to the end of the page, since the next page can be anywhere in physical memory
+            let page_left = 4096 - usize::from(addr.page_offset());
+            let len = page_left.min(data.len() - written);
+
+            let dest: *mut u8 = memory::phys_to_virt(phys).as_mut_ptr();
+            unsafe { dest.copy_from_nonoverlapping(data[written..].as_ptr(), len) };
+            written += len;
+        }
+
+        Ok(())
+    }
+
+    /// Copy the memory at `addr` into `buf`, like [`Self::write`] in reverse.
+    ///
+    /// # Errors
+    ///
+    /// Will error if any of the bytes are not mapped.
+    pub fn read(&self, addr: VirtAddr, buf: &mut [u8]) -> Result<(), VmError> {
+        let mut read = 0;
+        while read < buf.len() {
+            let addr = addr + read as u64;
+            let (phys, _) = self.translate(addr).ok_or(VmError::NotReserved)?;
+
+            let page_left = 4096 - usize::from(addr.page_offset());
+            let len = page_left.min(buf.len() - read);
+
+            let src: *const u8 = memory::phys_to_virt(phys).as_ptr();
+            unsafe { src.copy_to_nonoverlapping(buf[read..].as_mut_ptr(), len) };
+            read += len;
+        }
+
+        Ok(())
+    }
+
+    /// Change the flags of the mapped pages from `start` to `end` (exclusive).
+    ///
+    /// `USER_ACCESSIBLE` and `PRESENT` are always added to `flags`.
+    ///
+    /// # Errors
+    ///
+    /// Will error if the range is not page aligned and inside user space, or a page is not mapped.
+    pub fn set_flags(
+        &mut self,
+        start: VirtAddr,
+        end: VirtAddr,
+        flags: PageTableFlags,
+    ) -> Result<(), VmError> {
+        check_user_range(start, end)?;
+        let flags = flags | PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+        let mut mapper = unsafe { self.mapper() };
+
+        for page in pages(start, end) {
+            let flush = unsafe { mapper.update_flags(page, flags) }.map_err(VmError::FlagUpdate)?;
+            // the address space may be active
+            flush.flush();
+        }
+        Ok(())
+    }
+
+    /// A mapper for this address space's page tables, which are reached through the physical memory mapping.
+    ///
+    /// # Safety
+    ///
+    /// Only one mapper for this address space may exist at a time, which `&mut self` ensures.
+    unsafe fn mapper(&mut self) -> OffsetPageTable<'static> {
+        let phys_offset = memory::phys_to_virt(PhysAddr::new(0));
+        OffsetPageTable::new(&mut *table_ptr(self.p4), phys_offset)
+    }
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        if self.is_active() {
+            unsafe { activate_frame(memory::kernel_p4()) };
+        }
+
+        let table = unsafe { &mut *table_ptr(self.p4) };
+        memory::with_frame_allocator(|frame_allocator| {
+            for entry in table
+                .iter_mut()
+                .take(USER_P4_ENTRIES.end)
+                .skip(USER_P4_ENTRIES.start)
+            {
+                if let Ok(p3) = entry.frame() {
+                    unsafe { free_table(p3, 3, frame_allocator) };
+                }
+                entry.set_unused();
+            }
+            unsafe { frame_allocator.deallocate_frame(self.p4) };
+        });
+    }
+}
+
+/// Free every frame mapped by the table in `frame` at `level`, the tables below it, and the table itself
+///
+/// # Safety
+///
+/// The table must not be used by anything else anymore.
+unsafe fn free_table(frame: PhysFrame, level: u8, frame_allocator: &mut BitmapFrameAllocator) {
+    let table = &*table_ptr(frame);
+    for entry in table.iter() {
+        // huge pages are never mapped in user space, so every frame is either a page or a table
+        let Ok(child) = entry.frame() else {
+            continue;
+        };
+        if level > 1 {
+            free_table(child, level - 1, frame_allocator);
+        } else {
+            frame_allocator.deallocate_frame(child);
+        }
+    }
+
+    frame_allocator.deallocate_frame(frame);
+}
+
+/// Get the physical address `addr` is mapped to in the page tables that are currently in use, and the flags
+/// combined like [`AddressSpace::translate`] does.
+#[must_use]
+pub fn translate_active(addr: VirtAddr) -> Option<(PhysAddr, PageTableFlags)> {
+    translate_in(Cr3::read().0, addr)
+}
+
+/// Switch to the address space whose level 4 table is in `p4`, if it is not already active
+///
+/// # Safety
+///
+/// See [`AddressSpace::activate`]
+pub(crate) unsafe fn activate_frame(p4: PhysFrame) {
+    let (current, flags) = Cr3::read();
+    if current != p4 {
+        Cr3::write(p4, flags);
+    }
+}
+
+fn translate_in(p4: PhysFrame, addr: VirtAddr) -> Option<(PhysAddr, PageTableFlags)> {
+    let indexes = [
+        addr.p4_index(),
+        addr.p3_index(),
+        addr.p2_index(),
+        addr.p1_index(),
+    ];
+
+    // only keep the permissions every level agrees on
+    let mut flags = PageTableFlags::USER_ACCESSIBLE | PageTableFlags::WRITABLE;
+    let mut frame = p4;
+    for index in indexes {
+        let table = unsafe { &*table_ptr(frame) };
+        let entry = &table[index];
+
+        // also fails for huge pages, which are never mapped in user space
+        frame = entry.frame().ok()?;
+        let permissions = PageTableFlags::USER_ACCESSIBLE | PageTableFlags::WRITABLE;
+        flags = (flags & entry.flags()) | (entry.flags() - permissions);
+    }
+
+    Some((frame.start_address() + addr.as_u64() % PAGE_SIZE, flags))
+}
+
+fn table_ptr(frame: PhysFrame) -> *mut PageTable {
+    memory::phys_to_virt(frame.start_address()).as_mut_ptr()
+}
+
+fn check_user_range(start: VirtAddr, end: VirtAddr) -> Result<(), VmError> {
+    if start >= end
+        || !start.is_aligned(PAGE_SIZE)
+        || !end.is_aligned(PAGE_SIZE)
+        || start.as_u64() < USER_START
+        || end.as_u64() > USER_END
+    {
+        Err(VmError::InvalidRange)
+    } else {
+        Ok(())
+    }
+}
+
+fn pages(start: VirtAddr, end: VirtAddr) -> impl Iterator<Item = Page<Size4KiB>> {
+    Page::range(
+        Page::containing_address(start),
+        Page::containing_address(end),
+    )
+}
+
+fn map_zeroed(
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut BitmapFrameAllocator,
+    page: Page,
+    flags: PageTableFlags,
+) -> Result<(), MapToError<Size4KiB>> {
+    let frame = frame_allocator
+        .allocate_frame()
+        .ok_or(MapToError::FrameAllocationFailed)?;
+
+    let frame_ptr: *mut u8 = memory::phys_to_virt(frame.start_address()).as_mut_ptr();
+    unsafe { ptr::write_bytes(frame_ptr, 0, 4096) };
+
+    match unsafe { mapper.map_to(page, frame, flags, frame_allocator) } {
+        // not active, or only for pages that were not mapped yet, so nothing is cached
+        Ok(flush) => {
+            flush.ignore();
+            Ok(())
+        }
+        Err(err) => {
+            unsafe { frame_allocator.deallocate_frame(frame) };
+            Err(err)
+        }
+    }
+}
+
+fn unmap_page(
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut BitmapFrameAllocator,
+    page: Page,
+) -> Result<(), UnmapError> {
+    let (frame, flush) = mapper.unmap(page)?;
+    // the address space may be active
+    flush.flush();
+    unsafe { frame_allocator.deallocate_frame(frame) };
+    Ok(())
+}
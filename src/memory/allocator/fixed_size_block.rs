@@ -4,13 +4,29 @@ use core::{
     ptr::{self, NonNull},
 };
 
-use log::error;
+use log::{error, trace};
+use x86_64::instructions::interrupts;
 
-use super::Locked;
+use super::{tracking::Tracker, Locked};
 
 struct ListNode {
-    #[allow(dead_code)]
-    next: Option<&'static mut ListNode>,
+    next: Option<NonNull<ListNode>>,
+}
+
+/// Header at the start of every slab.
+///
+/// A slab is a naturally aligned chunk of memory taken from the fallback allocator and cut into blocks of one size
+/// class, so the slab of any block can be found by aligning the block's address down to the slab size.
+struct Slab {
+    /// Freed blocks of this slab
+    free_list: Option<NonNull<ListNode>>,
+    /// Index of the first block that was never handed out
+    next_unused: usize,
+    /// Amount of blocks not handed out
+    free: usize,
+    /// Neighbours in the list of slabs with free blocks
+    prev: Option<NonNull<Slab>>,
+    next: Option<NonNull<Slab>>,
 }
 
 /// Block sizes to use
@@ -18,9 +34,104 @@ struct ListNode {
 /// The sizes must be powers of 2 since we use them as block alignment too (alignment has to be power of 2)
 const BLOCK_SIZES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048];
 
+/// Amount of block size classes
+pub const SIZE_CLASSES: usize = BLOCK_SIZES.len();
+
+/// The minimum amount of bytes to grow the heap by, to avoid mapping pages one at a time
+const MIN_GROW_SIZE: usize = 64 * 1024; // 64 kib
+const PAGE_SIZE: usize = 4096;
+
+/// Size of the slabs for the size class at `index`. Always a power of 2 and at least one page.
+const fn slab_size(index: usize) -> usize {
+    let size = BLOCK_SIZES[index] * 8;
+    if size < PAGE_SIZE {
+        PAGE_SIZE
+    } else {
+        size
+    }
+}
+
+/// Amount of blocks at the start of a slab used by its header
+const fn header_blocks(index: usize) -> usize {
+    mem::size_of::<Slab>().div_ceil(BLOCK_SIZES[index])
+}
+
+/// Amount of blocks a slab of the size class at `index` can hand out
+const fn slab_capacity(index: usize) -> usize {
+    slab_size(index) / BLOCK_SIZES[index] - header_blocks(index)
+}
+
+fn slab_layout(index: usize) -> Layout {
+    Layout::from_size_align(slab_size(index), slab_size(index)).expect("slab sizes are powers of 2")
+}
+
+#[allow(clippy::struct_field_names)]
 pub struct Allocator {
-    list_heads: [Option<&'static mut ListNode>; BLOCK_SIZES.len()],
+    /// Slabs with at least one free block, per size class
+    partial_slabs: [Option<NonNull<Slab>>; BLOCK_SIZES.len()],
     fallback_allocator: linked_list_allocator::Heap,
+    /// End of the memory owned by the fallback allocator
+    heap_end: usize,
+    /// The size the heap may grow to
+    max_size: usize,
+    stats: HeapStats,
+    pub(super) tracker: Tracker,
+}
+
+// the raw pointers only point into the heap owned by the allocator
+unsafe impl Send for Allocator {}
+
+/// Counters for one block size class
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SizeClassStats {
+    pub block_size: usize,
+    /// Blocks currently handed out
+    pub live: usize,
+    /// Free blocks in this class's slabs, ready for reuse
+    pub cached: usize,
+    /// Slabs currently owned by this class
+    pub slabs: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    pub size_classes: [SizeClassStats; SIZE_CLASSES],
+    /// Live allocations too large for any size class, served by the fallback heap directly
+    pub large_allocations: usize,
+    /// Bytes handed out by the fallback heap, including slabs
+    pub fallback_used: usize,
+    /// Size of the memory owned by the fallback heap
+    pub heap_size: usize,
+    /// Bytes requested by live allocations
+    pub allocated_bytes: usize,
+    /// Highest value `allocated_bytes` has reached
+    pub peak_allocated_bytes: usize,
+}
+
+impl HeapStats {
+    const fn new() -> Self {
+        let mut size_classes = [SizeClassStats {
+            block_size: 0,
+            live: 0,
+            cached: 0,
+            slabs: 0,
+        }; SIZE_CLASSES];
+
+        let mut i = 0;
+        while i < SIZE_CLASSES {
+            size_classes[i].block_size = BLOCK_SIZES[i];
+            i += 1;
+        }
+
+        Self {
+            size_classes,
+            large_allocations: 0,
+            fallback_used: 0,
+            heap_size: 0,
+            allocated_bytes: 0,
+            peak_allocated_bytes: 0,
+        }
+    }
 }
 
 /// Get the index of the block size needed for a given `layout`
@@ -38,13 +149,53 @@ impl Default for Allocator {
 impl Allocator {
     #[must_use]
     pub const fn new() -> Self {
-        const EMPTY: Option<&'static mut ListNode> = None;
         Self {
-            list_heads: [EMPTY; BLOCK_SIZES.len()],
+            partial_slabs: [None; BLOCK_SIZES.len()],
             fallback_allocator: linked_list_allocator::Heap::empty(),
+            heap_end: 0,
+            max_size: 0,
+            stats: HeapStats::new(),
+            tracker: Tracker::new(),
         }
     }
 
+    /// Get a snapshot of this allocator's counters
+    #[must_use]
+    pub fn stats(&self) -> HeapStats {
+        HeapStats {
+            fallback_used: self.fallback_allocator.used(),
+            heap_size: self.heap_end - self.fallback_allocator.bottom() as usize,
+            ..self.stats
+        }
+    }
+
+    fn record_alloc(&mut self, ptr: *mut u8, layout: Layout) {
+        if ptr.is_null() {
+            return;
+        }
+
+        let stats = &mut self.stats;
+        match list_index(&layout) {
+            Some(idx) => stats.size_classes[idx].live += 1,
+            None => stats.large_allocations += 1,
+        }
+        stats.allocated_bytes += layout.size();
+        stats.peak_allocated_bytes = stats.peak_allocated_bytes.max(stats.allocated_bytes);
+
+        self.tracker.record_alloc(ptr, layout);
+    }
+
+    fn record_dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        let stats = &mut self.stats;
+        match list_index(&layout) {
+            Some(idx) => stats.size_classes[idx].live -= 1,
+            None => stats.large_allocations -= 1,
+        }
+        stats.allocated_bytes -= layout.size();
+
+        self.tracker.record_dealloc(ptr);
+    }
+
     /// Initialize the fallback allocator with the given heap bounds.
     ///
     /// # Safety
@@ -56,62 +207,213 @@ impl Allocator {
     pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
         self.fallback_allocator
             .init(heap_start as *mut u8, heap_size);
+        self.heap_end = heap_start + heap_size;
+        self.max_size = self.max_size.max(heap_size);
+    }
+
+    /// Set the size the heap may grow to when the fallback allocator runs out of memory
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
     }
 
     fn fallback_alloc(&mut self, layout: Layout) -> *mut u8 {
         if let Ok(ptr) = self.fallback_allocator.allocate_first_fit(layout) {
-            ptr.as_ptr()
+            return ptr.as_ptr();
+        }
+
+        if self.grow(layout) {
+            if let Ok(ptr) = self.fallback_allocator.allocate_first_fit(layout) {
+                return ptr.as_ptr();
+            }
+        }
+
+        error!(
+            "fallback allocator failed to allocate {} bytes",
+            layout.size()
+        );
+        ptr::null_mut()
+    }
+
+    /// Hand out a block of the size class at `index`, taking a new slab from the fallback allocator if needed
+    unsafe fn alloc_block(&mut self, index: usize) -> *mut u8 {
+        let slab = match self.partial_slabs[index] {
+            Some(slab) => slab,
+            None => match self.new_slab(index) {
+                Some(slab) => slab,
+                None => return ptr::null_mut(),
+            },
+        };
+        let slab_ptr = slab.as_ptr();
+
+        let block = if let Some(node) = (*slab_ptr).free_list {
+            (*slab_ptr).free_list = (*node.as_ptr()).next;
+            node.as_ptr().cast::<u8>()
         } else {
-            error!(
-                "fallback allocator failed to allocate {} bytes",
-                layout.size()
-            );
-            ptr::null_mut()
+            let block = (*slab_ptr).next_unused;
+            (*slab_ptr).next_unused += 1;
+            slab_ptr.cast::<u8>().add(block * BLOCK_SIZES[index])
+        };
+
+        (*slab_ptr).free -= 1;
+        if (*slab_ptr).free == 0 {
+            self.unlink_slab(index, slab);
+        }
+
+        self.stats.size_classes[index].cached -= 1;
+        block
+    }
+
+    /// Return a block of the size class at `index` to its slab, releasing the slab if it is now unused
+    unsafe fn dealloc_block(&mut self, ptr: *mut u8, index: usize) {
+        // verify that block has size and alignment required for storing node
+        assert!(mem::size_of::<ListNode>() <= BLOCK_SIZES[index]);
+        assert!(mem::align_of::<ListNode>() <= BLOCK_SIZES[index]);
+
+        let slab_addr = ptr as usize & !(slab_size(index) - 1);
+        let slab = NonNull::new_unchecked(slab_addr as *mut Slab);
+        let slab_ptr = slab.as_ptr();
+
+        #[allow(clippy::cast_ptr_alignment)]
+        let node = ptr.cast::<ListNode>();
+        node.write(ListNode {
+            next: (*slab_ptr).free_list,
+        });
+        (*slab_ptr).free_list = Some(NonNull::new_unchecked(node));
+
+        (*slab_ptr).free += 1;
+        self.stats.size_classes[index].cached += 1;
+
+        if (*slab_ptr).free == 1 {
+            // the slab was full, so it is not in the list yet
+            self.link_slab(index, slab);
+        }
+
+        // keep the last slab of a class around so a single block being allocated and freed in a loop
+        // does not take a new slab each time
+        let is_only_slab = (*slab_ptr).prev.is_none() && (*slab_ptr).next.is_none();
+        if (*slab_ptr).free == slab_capacity(index) && !is_only_slab {
+            self.release_slab(index, slab);
         }
     }
-}
 
-unsafe impl GlobalAlloc for Locked<Allocator> {
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        let mut allocator = self.lock();
+    unsafe fn new_slab(&mut self, index: usize) -> Option<NonNull<Slab>> {
+        // slabs are aligned to their size, which is at least a page
+        #[allow(clippy::cast_ptr_alignment)]
+        let slab = NonNull::new(self.fallback_alloc(slab_layout(index)).cast::<Slab>())?;
 
-        if let Some(idx) = list_index(&layout) {
-            if let Some(node) = allocator.list_heads[idx].take() {
-                ptr::from_mut::<ListNode>(node).cast::<u8>()
-            } else {
-                let block_size = BLOCK_SIZES[idx];
-                let block_align = block_size;
+        slab.as_ptr().write(Slab {
+            free_list: None,
+            next_unused: header_blocks(index),
+            free: slab_capacity(index),
+            prev: None,
+            next: None,
+        });
+        self.link_slab(index, slab);
 
-                let layout = Layout::from_size_align(block_size, block_align)
-                    .expect("failed to create layout when allocating");
-                allocator.fallback_alloc(layout)
-            }
-        } else {
-            allocator.fallback_alloc(layout)
+        let stats = &mut self.stats.size_classes[index];
+        stats.slabs += 1;
+        stats.cached += slab_capacity(index);
+
+        Some(slab)
+    }
+
+    unsafe fn release_slab(&mut self, index: usize, slab: NonNull<Slab>) {
+        self.unlink_slab(index, slab);
+        self.fallback_allocator
+            .deallocate(slab.cast::<u8>(), slab_layout(index));
+
+        let stats = &mut self.stats.size_classes[index];
+        stats.slabs -= 1;
+        stats.cached -= slab_capacity(index);
+    }
+
+    /// Push `slab` to the front of the list of slabs with free blocks
+    unsafe fn link_slab(&mut self, index: usize, slab: NonNull<Slab>) {
+        let head = self.partial_slabs[index];
+
+        (*slab.as_ptr()).prev = None;
+        (*slab.as_ptr()).next = head;
+        if let Some(head) = head {
+            (*head.as_ptr()).prev = Some(slab);
         }
+
+        self.partial_slabs[index] = Some(slab);
     }
 
-    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        let mut allocator = self.lock();
+    unsafe fn unlink_slab(&mut self, index: usize, slab: NonNull<Slab>) {
+        let Slab { prev, next, .. } = *slab.as_ptr();
 
-        if let Some(index) = list_index(&layout) {
-            let new_node = ListNode {
-                next: allocator.list_heads[index].take(),
-            };
+        match prev {
+            Some(prev) => (*prev.as_ptr()).next = next,
+            None => self.partial_slabs[index] = next,
+        }
+        if let Some(next) = next {
+            (*next.as_ptr()).prev = prev;
+        }
 
-            // verify that block has size and alignment required for storing node
-            assert!(mem::size_of::<ListNode>() <= BLOCK_SIZES[index]);
-            assert!(mem::align_of::<ListNode>() <= BLOCK_SIZES[index]);
+        (*slab.as_ptr()).prev = None;
+        (*slab.as_ptr()).next = None;
+    }
 
-            #[allow(clippy::cast_ptr_alignment)]
-            let new_node_ptr = ptr.cast::<ListNode>();
-            new_node_ptr.write(new_node);
+    /// Map more pages at the end of the heap so that an allocation of `layout` fits.
+    ///
+    /// Returns whether the heap was grown.
+    fn grow(&mut self, layout: Layout) -> bool {
+        // the end of the heap may not be free, so the new memory alone must fit the allocation
+        let needed = layout.size() + layout.align();
+        let grow_by = needed.max(MIN_GROW_SIZE).next_multiple_of(PAGE_SIZE);
 
-            allocator.list_heads[index] = Some(&mut *new_node_ptr);
-        } else {
-            let ptr = NonNull::new(ptr).unwrap();
+        let heap_size = self.heap_end - self.fallback_allocator.bottom() as usize;
+        let available = self.max_size.saturating_sub(heap_size) / PAGE_SIZE * PAGE_SIZE;
+        let grow_by = grow_by.min(available);
+
+        if grow_by < needed {
+            error!("heap cannot grow past {} bytes", self.max_size);
+            return false;
+        }
 
-            allocator.fallback_allocator.deallocate(ptr, layout);
+        if let Err(err) = super::map_heap_pages(self.heap_end, grow_by) {
+            error!("failed to map pages to grow heap: {err:?}");
+            return false;
         }
+
+        trace!("growing heap by {grow_by} bytes");
+        unsafe { self.fallback_allocator.extend(grow_by) };
+        self.heap_end += grow_by;
+
+        true
+    }
+}
+
+// interrupts are disabled while the lock is held, so a preempted thread never holds it
+unsafe impl GlobalAlloc for Locked<Allocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        interrupts::without_interrupts(|| {
+            let mut allocator = self.lock();
+
+            let ptr = if let Some(idx) = list_index(&layout) {
+                allocator.alloc_block(idx)
+            } else {
+                allocator.fallback_alloc(layout)
+            };
+
+            allocator.record_alloc(ptr, layout);
+            ptr
+        })
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        interrupts::without_interrupts(|| {
+            let mut allocator = self.lock();
+            allocator.record_dealloc(ptr, layout);
+
+            if let Some(index) = list_index(&layout) {
+                allocator.dealloc_block(ptr, index);
+            } else {
+                let ptr = NonNull::new(ptr).unwrap();
+
+                allocator.fallback_allocator.deallocate(ptr, layout);
+            }
+        });
     }
 }
@@ -1,17 +1,20 @@
 pub mod fixed_size_block;
+pub mod tracking;
 
-use core::ops::Deref;
+use core::{ops::Deref, panic::Location};
 
 use log::trace;
-use x86_64::{
-    structures::paging::{
-        mapper::MapToError, FrameAllocator, Mapper, Page, PageTableFlags, Size4KiB,
-    },
-    VirtAddr,
-};
+use x86_64::{instructions::interrupts, structures::paging::PageTableFlags, VirtAddr};
+
+use crate::memory::vm::{self, RegionKind, VmError};
+
+pub use fixed_size_block::{HeapStats, SizeClassStats};
+use tracking::LeakReport;
 
 pub const HEAP_START: usize = 0x4444_4444_0000;
 pub const HEAP_SIZE: usize = 100 * 1024; // 100 kib
+/// The default size the heap may grow to when it runs out of memory
+pub const HEAP_MAX_SIZE: usize = 64 * 1024 * 1024; // 64 mib
 
 #[global_allocator]
 static ALLOCATOR: Locked<fixed_size_block::Allocator> =
@@ -41,36 +44,80 @@ impl<A> Deref for Locked<A> {
 ///
 /// # Errors
 ///
-/// Will error if the memory mapping fails. See [`MapToError`]
-pub fn init_heap(
-    mapper: &mut impl Mapper<Size4KiB>,
-    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
-) -> Result<(), MapToError<Size4KiB>> {
+/// Will error if reserving or mapping the heap's virtual memory fails. See [`VmError`]
+pub fn init_heap() -> Result<(), VmError> {
     trace!("initialising heap");
-    let page_range = {
-        let heap_start = VirtAddr::new(HEAP_START as u64);
-        let heap_end = heap_start + HEAP_SIZE as u64 - 1u64;
+    let start = VirtAddr::new(HEAP_START as u64);
+    vm::reserve(
+        start,
+        start + HEAP_MAX_SIZE as u64,
+        PageTableFlags::WRITABLE,
+        RegionKind::Heap,
+        false,
+    )?;
+    map_heap_pages(HEAP_START, HEAP_SIZE)?;
+
+    let mut allocator = ALLOCATOR.lock();
+    unsafe {
+        allocator.init(HEAP_START, HEAP_SIZE);
+    }
+    allocator.set_max_size(HEAP_MAX_SIZE);
 
-        let heap_start_page = Page::containing_address(heap_start);
-        let heap_end_page = Page::containing_address(heap_end);
+    Ok(())
+}
 
-        Page::range_inclusive(heap_start_page, heap_end_page)
-    };
+/// Set the size the heap may grow to. The heap never shrinks, so a limit below the current size only stops further growth.
+///
+/// The limit is capped at [`HEAP_MAX_SIZE`], since that is all the virtual memory reserved for the heap.
+pub fn set_heap_limit(max_size: usize) {
+    let max_size = max_size.min(HEAP_MAX_SIZE);
+    interrupts::without_interrupts(|| ALLOCATOR.lock().set_max_size(max_size));
+}
 
-    for page in page_range {
-        let frame = frame_allocator
-            .allocate_frame()
-            .ok_or(MapToError::FrameAllocationFailed)?;
-        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+/// Get a snapshot of the heap allocator's counters
+#[must_use]
+pub fn heap_stats() -> HeapStats {
+    interrupts::without_interrupts(|| ALLOCATOR.lock().stats())
+}
 
-        unsafe {
-            mapper.map_to(page, frame, flags, frame_allocator)?.flush();
-        }
+/// Start recording every allocation made until [`LeakTracker::finish`] is called.
+///
+/// Every allocation is recorded with a backtrace of where it was made, and as tracked from the caller of this.
+///
+/// Only one tracker should be active at a time, starting another one discards what the previous one recorded.
+#[track_caller]
+#[must_use = "tracking stops when the tracker is dropped"]
+pub fn track_allocations() -> LeakTracker {
+    let tracked_from = Location::caller();
+    interrupts::without_interrupts(|| ALLOCATOR.lock().tracker.start(tracked_from));
+
+    LeakTracker { finished: false }
+}
+
+/// Records allocations while alive, see [`track_allocations`]
+pub struct LeakTracker {
+    finished: bool,
+}
+
+impl LeakTracker {
+    /// Stop tracking and report the allocations that were made but not freed since tracking started
+    #[must_use]
+    pub fn finish(mut self) -> LeakReport {
+        self.finished = true;
+        interrupts::without_interrupts(|| ALLOCATOR.lock().tracker.finish())
     }
+}
 
-    unsafe {
-        ALLOCATOR.lock().init(HEAP_START, HEAP_SIZE);
+impl Drop for LeakTracker {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = interrupts::without_interrupts(|| ALLOCATOR.lock().tracker.finish());
+        }
     }
+}
 
-    Ok(())
+/// Map fresh frames to the `size` bytes starting at `start`, which must be inside the heap's region
+fn map_heap_pages(start: usize, size: usize) -> Result<(), VmError> {
+    let start = VirtAddr::new(start as u64);
+    vm::map_range(start, start + size as u64)
 }
@@ -0,0 +1,233 @@
+use core::{alloc::Layout, arch::asm, fmt, panic::Location};
+
+use x86_64::{structures::paging::Translate, VirtAddr};
+
+use crate::memory;
+
+/// Maximum amount of live allocations recorded while tracking
+const MAX_TRACKED: usize = 256;
+
+/// How many leaked allocations a [`LeakReport`] lists individually
+const REPORTED_LEAKS: usize = 8;
+
+/// How many return addresses are recorded per allocation
+pub const BACKTRACE_DEPTH: usize = 12;
+
+/// Return addresses of the calls that led to an allocation, innermost first, ending in zeroes if the stack was
+/// shallower than [`BACKTRACE_DEPTH`]. The first few are inside the allocator itself.
+///
+/// Resolve them with `addr2line -e` on the kernel binary.
+pub type Backtrace = [usize; BACKTRACE_DEPTH];
+
+/// A live allocation recorded while tracking was enabled
+#[derive(Debug, Clone, Copy)]
+pub struct Allocation {
+    pub addr: usize,
+    pub size: usize,
+    pub align: usize,
+    /// Position of this allocation in the order allocations were made since tracking started
+    pub sequence: u64,
+    /// Where [`track_allocations`](super::track_allocations) was called to start the tracking this was recorded by
+    pub tracked_from: &'static Location<'static>,
+    /// Where the allocation was made
+    pub backtrace: Backtrace,
+}
+
+/// Records live allocations so that leaks can be found
+pub(super) struct Tracker {
+    /// Where tracking was started, `None` while not tracking
+    tracked_from: Option<&'static Location<'static>>,
+    allocations: [Option<Allocation>; MAX_TRACKED],
+    /// Allocations that could not be recorded because the table was full
+    untracked: usize,
+    next_sequence: u64,
+}
+
+impl Tracker {
+    #[allow(
+        clippy::large_stack_arrays,
+        reason = "only used to initialise the allocator's static, at compile time"
+    )]
+    pub(super) const fn new() -> Self {
+        Self {
+            tracked_from: None,
+            allocations: [None; MAX_TRACKED],
+            untracked: 0,
+            next_sequence: 0,
+        }
+    }
+
+    /// Start recording allocations made after this call
+    pub(super) fn start(&mut self, tracked_from: &'static Location<'static>) {
+        self.allocations.fill(None);
+        self.untracked = 0;
+        self.next_sequence = 0;
+        self.tracked_from = Some(tracked_from);
+    }
+
+    /// Stop recording and report every recorded allocation that was not freed
+    pub(super) fn finish(&mut self) -> LeakReport {
+        self.tracked_from = None;
+
+        let mut report = LeakReport {
+            leaked: 0,
+            leaked_bytes: 0,
+            untracked: self.untracked,
+            first: [None; REPORTED_LEAKS],
+        };
+
+        for allocation in self.allocations.iter_mut().filter_map(Option::take) {
+            if let Some(slot) = report.first.get_mut(report.leaked) {
+                *slot = Some(allocation);
+            }
+            report.leaked += 1;
+            report.leaked_bytes += allocation.size;
+        }
+
+        report
+    }
+
+    pub(super) fn record_alloc(&mut self, ptr: *mut u8, layout: Layout) {
+        let Some(tracked_from) = self.tracked_from else {
+            return;
+        };
+
+        let allocation = Allocation {
+            addr: ptr as usize,
+            size: layout.size(),
+            align: layout.align(),
+            sequence: self.next_sequence,
+            tracked_from,
+            backtrace: backtrace(),
+        };
+        self.next_sequence += 1;
+
+        if let Some(slot) = self.allocations.iter_mut().find(|a| a.is_none()) {
+            *slot = Some(allocation);
+        } else {
+            self.untracked += 1;
+        }
+    }
+
+    pub(super) fn record_dealloc(&mut self, ptr: *mut u8) {
+        if self.tracked_from.is_none() {
+            return;
+        }
+
+        let addr = ptr as usize;
+        if let Some(slot) = self
+            .allocations
+            .iter_mut()
+            .find(|a| a.is_some_and(|a| a.addr == addr))
+        {
+            *slot = None;
+        }
+    }
+}
+
+/// Walk the frame pointer chain from the caller, which the kernel target always keeps.
+///
+/// Stops at the first frame pointer that is misaligned, not mapped, or not above the one before it,
+/// since the chain ends in whatever the bootloader or a new thread's stack left in `rbp`.
+#[inline(never)]
+fn backtrace() -> Backtrace {
+    let mut backtrace = [0; BACKTRACE_DEPTH];
+
+    let mut frame: usize;
+    unsafe { asm!("mov {}, rbp", out(reg) frame, options(nomem, nostack, preserves_flags)) };
+
+    for slot in &mut backtrace {
+        // a frame is the caller's frame pointer followed by the return address
+        if frame == 0 || !frame.is_multiple_of(8) || !is_mapped(frame) || !is_mapped(frame + 8) {
+            break;
+        }
+        let next = unsafe { *(frame as *const usize) };
+        *slot = unsafe { *((frame + 8) as *const usize) };
+
+        if next <= frame {
+            break;
+        }
+        frame = next;
+    }
+
+    backtrace
+}
+
+/// Whether reading `addr` would not page fault. Says no if the page tables are being changed right now.
+fn is_mapped(addr: usize) -> bool {
+    let Ok(addr) = VirtAddr::try_new(addr as u64) else {
+        return false;
+    };
+    memory::try_with_mapper(|mapper, _| mapper.translate_addr(addr).is_some()).unwrap_or(false)
+}
+
+/// The allocations that were still live when tracking finished
+#[derive(Clone, Copy)]
+pub struct LeakReport {
+    leaked: usize,
+    leaked_bytes: usize,
+    untracked: usize,
+    first: [Option<Allocation>; REPORTED_LEAKS],
+}
+
+impl LeakReport {
+    /// Whether every tracked allocation was freed
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.leaked == 0
+    }
+
+    /// Amount of allocations that were not freed
+    #[must_use]
+    pub fn leaked(&self) -> usize {
+        self.leaked
+    }
+
+    #[must_use]
+    pub fn leaked_bytes(&self) -> usize {
+        self.leaked_bytes
+    }
+
+    /// Amount of allocations that were not recorded because too many allocations were live at once.
+    ///
+    /// If this is not zero, leaks may have been missed.
+    #[must_use]
+    pub fn untracked(&self) -> usize {
+        self.untracked
+    }
+
+    /// The first few leaked allocations
+    pub fn allocations(&self) -> impl Iterator<Item = &Allocation> {
+        self.first.iter().flatten()
+    }
+}
+
+impl fmt::Debug for LeakReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} leaked allocations ({} bytes), {} untracked",
+            self.leaked, self.leaked_bytes, self.untracked
+        )?;
+        for allocation in self.allocations() {
+            writeln!(
+                f,
+                "  #{} at {:#x}: {} bytes (align {}), tracked from {}",
+                allocation.sequence,
+                allocation.addr,
+                allocation.size,
+                allocation.align,
+                allocation.tracked_from
+            )?;
+            write!(f, "    allocated from")?;
+            for addr in allocation.backtrace.iter().take_while(|&&addr| addr != 0) {
+                write!(f, " {addr:#x}")?;
+            }
+            writeln!(f)?;
+        }
+        if self.leaked > REPORTED_LEAKS {
+            writeln!(f, "  ...and {} more", self.leaked - REPORTED_LEAKS)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,251 @@
+use core::{ops::Range, slice};
+
+use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use log::trace;
+use x86_64::{
+    structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB},
+    PhysAddr, VirtAddr,
+};
+
+const FRAME_SIZE: u64 = 4096;
+const BITS_PER_WORD: usize = u64::BITS as usize;
+
+/// A physical frame allocator that tracks every frame with one bit (set = in use)
+///
+/// The bitmaps live in the first usable region large enough to hold them, and their frames are marked as used.
+pub struct BitmapFrameAllocator {
+    bitmap: &'static mut [u64],
+    /// One bit per frame, set if the bootloader marked it as `Usable`.
+    /// Reserved frames inside the bitmap, like the VGA buffer, can never be freed.
+    usable: &'static mut [u64],
+    /// Amount of frames marked as `Usable` by the bootloader
+    total: usize,
+    used: usize,
+    /// Index of the word to start searching from, every word before it is full
+    next_word: usize,
+}
+
+impl BitmapFrameAllocator {
+    /// Create a [`BitmapFrameAllocator`] from a passed [`MemoryMap`]
+    ///
+    /// # Safety
+    ///
+    /// Caller must guarantee that:
+    /// - The passed memory map is valid. (all frames marked as `Usable` are actually unused.)
+    /// - The complete physical memory is mapped to virtual memory at `phys_offset`.
+    /// - This function is called only once.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if no usable region is large enough to hold the bitmap.
+    #[must_use]
+    pub unsafe fn new(memory_map: &'static MemoryMap, phys_offset: VirtAddr) -> Self {
+        let usable = || {
+            memory_map
+                .iter()
+                .filter(|r| r.region_type == MemoryRegionType::Usable)
+        };
+
+        let frame_count = usable()
+            .map(|r| r.range.end_frame_number)
+            .max()
+            .unwrap_or(0);
+        let frame_count = usize::try_from(frame_count).expect("frame count does not fit in usize");
+
+        let words = frame_count.div_ceil(BITS_PER_WORD);
+        // the used and the usable bitmap
+        let bitmap_bytes = (2 * words * size_of::<u64>()) as u64;
+
+        let bitmap_region = usable()
+            .find(|r| r.range.end_addr() - r.range.start_addr() >= bitmap_bytes)
+            .expect("no usable region can fit the frame bitmap");
+        let bitmap_start = PhysAddr::new(bitmap_region.range.start_addr());
+
+        trace!("placing frame bitmap ({bitmap_bytes} bytes) at {bitmap_start:?}");
+
+        let bitmap_ptr: *mut u64 = (phys_offset + bitmap_start.as_u64()).as_mut_ptr();
+        let (bitmap, usable_bitmap) =
+            slice::from_raw_parts_mut(bitmap_ptr, 2 * words).split_at_mut(words);
+
+        // everything is used until proven usable
+        bitmap.fill(u64::MAX);
+        usable_bitmap.fill(0);
+
+        let mut allocator = Self {
+            bitmap,
+            usable: usable_bitmap,
+            total: 0,
+            used: 0,
+            next_word: 0,
+        };
+
+        for region in usable() {
+            allocator.add_usable(region.range.start_frame_number..region.range.end_frame_number);
+        }
+
+        let bitmap_frames = bitmap_bytes.div_ceil(FRAME_SIZE);
+        let first_bitmap_frame = bitmap_start.as_u64() / FRAME_SIZE;
+        for frame in first_bitmap_frame..first_bitmap_frame + bitmap_frames {
+            allocator.set_used(frame_index(frame));
+            allocator.used += 1;
+        }
+
+        allocator
+    }
+
+    /// Amount of frames managed by this allocator
+    #[must_use]
+    pub fn total_frames(&self) -> usize {
+        self.total
+    }
+
+    /// Amount of frames currently allocated
+    #[must_use]
+    pub fn used_frames(&self) -> usize {
+        self.used
+    }
+
+    /// Amount of frames available for allocation
+    #[must_use]
+    pub fn free_frames(&self) -> usize {
+        self.total - self.used
+    }
+
+    /// Mark `frames` as usable and free, and count them towards the total
+    fn add_usable(&mut self, frames: Range<u64>) {
+        for frame in frames {
+            let index = frame_index(frame);
+            self.usable[index / BITS_PER_WORD] |= 1 << (index % BITS_PER_WORD);
+            self.set_free(index);
+            self.total += 1;
+        }
+    }
+
+    fn is_usable(&self, index: usize) -> bool {
+        self.usable[index / BITS_PER_WORD] & (1 << (index % BITS_PER_WORD)) != 0
+    }
+
+    fn is_used(&self, index: usize) -> bool {
+        self.bitmap[index / BITS_PER_WORD] & (1 << (index % BITS_PER_WORD)) != 0
+    }
+
+    fn set_used(&mut self, index: usize) {
+        self.bitmap[index / BITS_PER_WORD] |= 1 << (index % BITS_PER_WORD);
+    }
+
+    fn set_free(&mut self, index: usize) {
+        self.bitmap[index / BITS_PER_WORD] &= !(1 << (index % BITS_PER_WORD));
+    }
+}
+
+fn frame_index(frame_number: u64) -> usize {
+    usize::try_from(frame_number).expect("frame number does not fit in usize")
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        let (word_index, word) = self
+            .bitmap
+            .iter()
+            .enumerate()
+            .skip(self.next_word)
+            .find(|(_, &word)| word != u64::MAX)?;
+
+        let index = word_index * BITS_PER_WORD + word.trailing_ones() as usize;
+
+        self.set_used(index);
+        self.used += 1;
+        self.next_word = word_index;
+
+        let addr = PhysAddr::new(index as u64 * FRAME_SIZE);
+        Some(PhysFrame::containing_address(addr))
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BitmapFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size4KiB>) {
+        let index = frame_index(frame.start_address().as_u64() / FRAME_SIZE);
+
+        // frames past the end of the memory map or reserved by it, like mmio, were never allocated
+        if index / BITS_PER_WORD >= self.bitmap.len() || !self.is_usable(index) {
+            return;
+        }
+
+        debug_assert!(self.is_used(index), "double free of {frame:?}");
+        if !self.is_used(index) {
+            return;
+        }
+
+        self.set_free(index);
+        self.used -= 1;
+        self.next_word = self.next_word.min(index / BITS_PER_WORD);
+    }
+}
+
+/// An allocator for frames `frames`, with bitmaps in the heap
+#[cfg(test)]
+fn test_allocator(frames: Range<u64>) -> BitmapFrameAllocator {
+    let words = frame_index(frames.end).div_ceil(BITS_PER_WORD);
+    let bitmap = alloc::vec![u64::MAX; words].leak();
+    let usable = alloc::vec![0; words].leak();
+    let mut allocator = BitmapFrameAllocator {
+        bitmap,
+        usable,
+        total: 0,
+        used: 0,
+        next_word: 0,
+    };
+    allocator.add_usable(frames);
+    allocator
+}
+
+#[cfg(test)]
+fn frame(number: u64) -> PhysFrame {
+    PhysFrame::containing_address(PhysAddr::new(number * FRAME_SIZE))
+}
+
+#[test_case]
+fn test_allocate_and_free() {
+    let mut allocator = test_allocator(10..100);
+    assert_eq!(allocator.total_frames(), 90);
+    assert_eq!(allocator.used_frames(), 0);
+
+    let first = allocator.allocate_frame();
+    assert_eq!(first, Some(frame(10)));
+    assert_eq!(allocator.used_frames(), 1);
+    assert_eq!(allocator.free_frames(), 89);
+
+    for number in 11..100 {
+        assert_eq!(allocator.allocate_frame(), Some(frame(number)));
+    }
+    assert_eq!(allocator.allocate_frame(), None);
+    assert_eq!(allocator.free_frames(), 0);
+
+    // freed frames are handed out again
+    unsafe { allocator.deallocate_frame(frame(42)) };
+    assert_eq!(allocator.free_frames(), 1);
+    assert_eq!(allocator.allocate_frame(), Some(frame(42)));
+    assert_eq!(allocator.used_frames(), 90);
+}
+
+#[test_case]
+fn test_free_outside_memory_map() {
+    let mut allocator = test_allocator(0..64);
+    allocator.allocate_frame();
+
+    unsafe { allocator.deallocate_frame(frame(0xfee00)) };
+    assert_eq!(allocator.used_frames(), 1);
+    assert_eq!(allocator.free_frames(), 63);
+}
+
+#[test_case]
+fn test_free_reserved_frame() {
+    let mut allocator = test_allocator(10..100);
+    allocator.allocate_frame();
+
+    // frame 5 is inside the bitmap, but was never usable
+    unsafe { allocator.deallocate_frame(frame(5)) };
+    assert_eq!(allocator.used_frames(), 1);
+    assert_eq!(allocator.free_frames(), 89);
+    assert_eq!(allocator.allocate_frame(), Some(frame(11)));
+}
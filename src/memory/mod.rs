@@ -1,2 +1,123 @@
+pub mod address_space;
 pub mod allocator;
+pub mod frame;
 pub mod paging;
+pub mod stack;
+pub mod vm;
+
+use bootloader::BootInfo;
+use conquer_once::spin::OnceCell;
+use spin::Mutex;
+use x86_64::{
+    instructions::interrupts,
+    registers::control::Cr3,
+    structures::paging::{OffsetPageTable, PhysFrame},
+    PhysAddr, VirtAddr,
+};
+
+use frame::BitmapFrameAllocator;
+
+static PHYS_OFFSET: OnceCell<VirtAddr> = OnceCell::uninit();
+static MAPPER: OnceCell<Mutex<OffsetPageTable<'static>>> = OnceCell::uninit();
+static FRAME_ALLOCATOR: OnceCell<Mutex<BitmapFrameAllocator>> = OnceCell::uninit();
+/// The level 4 table the bootloader set up, which [`MAPPER`] manages
+static KERNEL_P4: OnceCell<PhysFrame> = OnceCell::uninit();
+
+/// Initialize the kernel's page table mapper and frame allocator
+///
+/// # Safety
+///
+/// - This function may only be called once.
+/// - The bootloader must have mapped the complete physical memory at `boot_info.physical_memory_offset`.
+///
+/// # Panics
+///
+/// Will panic if called more than once.
+pub unsafe fn init(boot_info: &'static BootInfo) {
+    let phys_offset = VirtAddr::new(boot_info.physical_memory_offset);
+
+    let mapper = paging::init_offset_table(phys_offset);
+    let frame_allocator = BitmapFrameAllocator::new(&boot_info.memory_map, phys_offset);
+
+    PHYS_OFFSET
+        .try_init_once(|| phys_offset)
+        .expect("memory initialised twice");
+    KERNEL_P4
+        .try_init_once(|| Cr3::read().0)
+        .expect("memory initialised twice");
+    MAPPER
+        .try_init_once(|| Mutex::new(mapper))
+        .expect("memory initialised twice");
+    FRAME_ALLOCATOR
+        .try_init_once(|| Mutex::new(frame_allocator))
+        .expect("memory initialised twice");
+}
+
+/// Run `f` with the kernel's mapper and frame allocator locked.
+///
+/// Interrupts are disabled while `f` runs, so it must not block. It also must not allocate heap memory,
+/// since growing the heap needs these same locks.
+///
+/// # Panics
+///
+/// Will panic if [`init`] was not called.
+pub fn with_mapper<R>(
+    f: impl FnOnce(&mut OffsetPageTable<'static>, &mut BitmapFrameAllocator) -> R,
+) -> R {
+    let mapper = MAPPER.try_get().expect("memory not initialised");
+    let frame_allocator = FRAME_ALLOCATOR.try_get().expect("memory not initialised");
+
+    interrupts::without_interrupts(|| f(&mut mapper.lock(), &mut frame_allocator.lock()))
+}
+
+/// Like [`with_mapper`], but returns `None` instead of waiting if the mapper or frame allocator is already locked.
+///
+/// # Panics
+///
+/// Will panic if [`init`] was not called.
+pub fn try_with_mapper<R>(
+    f: impl FnOnce(&mut OffsetPageTable<'static>, &mut BitmapFrameAllocator) -> R,
+) -> Option<R> {
+    let mapper = MAPPER.try_get().expect("memory not initialised");
+    let frame_allocator = FRAME_ALLOCATOR.try_get().expect("memory not initialised");
+
+    interrupts::without_interrupts(|| {
+        let mut mapper = mapper.try_lock()?;
+        let mut frame_allocator = frame_allocator.try_lock()?;
+        Some(f(&mut mapper, &mut frame_allocator))
+    })
+}
+
+/// Run `f` with only the frame allocator locked, for page tables other than the kernel's.
+///
+/// Interrupts are disabled while `f` runs, so it must not block or allocate heap memory.
+///
+/// # Panics
+///
+/// Will panic if [`init`] was not called.
+pub fn with_frame_allocator<R>(f: impl FnOnce(&mut BitmapFrameAllocator) -> R) -> R {
+    let frame_allocator = FRAME_ALLOCATOR.try_get().expect("memory not initialised");
+
+    interrupts::without_interrupts(|| f(&mut frame_allocator.lock()))
+}
+
+/// Get the frame of the kernel's level 4 table, which is active whenever no [`address_space::AddressSpace`] is
+///
+/// # Panics
+///
+/// Will panic if [`init`] was not called.
+#[must_use]
+pub fn kernel_p4() -> PhysFrame {
+    *KERNEL_P4.try_get().expect("memory not initialised")
+}
+
+/// Get the virtual address `phys` is mapped to in the complete physical memory mapping
+///
+/// # Panics
+///
+/// Will panic if [`init`] was not called.
+#[must_use]
+pub fn phys_to_virt(phys: PhysAddr) -> VirtAddr {
+    let phys_offset = PHYS_OFFSET.try_get().expect("memory not initialised");
+    *phys_offset + phys.as_u64()
+}
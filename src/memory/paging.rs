@@ -1,4 +1,3 @@
-use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
 use x86_64::{
     registers::control::Cr3,
     structures::paging::{
@@ -20,50 +19,6 @@ unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut
     &mut *page_table_ptr
 }
 
-pub struct BootInfoFrameAllocator {
-    memory_map: &'static MemoryMap,
-    next: usize,
-}
-
-impl BootInfoFrameAllocator {
-    /// Create a [`FrameAllocator`] from a passed [`MemoryMap`]
-    ///
-    /// # Safety
-    ///
-    /// Caller must guarantee the passed memory map is valid. (all frames marked as `Usable` are actually unused.)
-    #[must_use]
-    pub unsafe fn new(memory_map: &'static MemoryMap) -> Self {
-        Self {
-            memory_map,
-            next: 0,
-        }
-    }
-
-    /// Get the usable frames from the mapped memory regions
-    fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
-        let usable = self
-            .memory_map
-            .iter()
-            .filter(|r| r.region_type == MemoryRegionType::Usable);
-
-        let frame_addrs = usable
-            // address regions
-            .map(|r| r.range.start_addr()..r.range.end_addr())
-            // step 4 kib (4096 bytes) to find the frame start addrs
-            .flat_map(|r| r.step_by(4096));
-
-        frame_addrs.map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)))
-    }
-}
-
-unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
-    fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
-        let frame = self.usable_frames().nth(self.next);
-        self.next += 1;
-        frame
-    }
-}
-
 /// Initialize a [`OffsetPageTable`]
 ///
 /// # Safety
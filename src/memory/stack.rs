@@ -0,0 +1,82 @@
+use log::error;
+use x86_64::{structures::paging::PageTableFlags, VirtAddr};
+
+use super::vm::{self, Region, RegionKind, VmError};
+
+/// Default size of kernel stacks, without the guard page
+pub const DEFAULT_STACK_SIZE: u64 = 4096 * 5;
+
+/// A mapped kernel stack with an unmapped guard page below it, so that overflowing it causes a page fault
+/// instead of corrupting whatever is below.
+///
+/// The stack is unmapped and its frames freed when dropped.
+#[derive(Debug)]
+pub struct KernelStack {
+    guard: Region,
+    stack: Region,
+}
+
+impl KernelStack {
+    /// Allocate a stack of `size` bytes, rounded up to whole pages
+    ///
+    /// # Errors
+    ///
+    /// Will error if reserving or mapping the stack's virtual memory fails. See [`VmError`]
+    pub fn new(size: u64) -> Result<Self, VmError> {
+        let (guard, stack) =
+            vm::reserve_guarded(size, PageTableFlags::WRITABLE, RegionKind::Stack)?;
+
+        if let Err(err) = vm::map_range(stack.start, stack.end) {
+            let _ = vm::release(stack.start);
+            let _ = vm::release(guard.start);
+            return Err(err);
+        }
+
+        Ok(Self { guard, stack })
+    }
+
+    /// The initial stack pointer, since the stack grows down
+    #[must_use]
+    pub fn top(&self) -> VirtAddr {
+        self.stack.end
+    }
+
+    /// The lowest usable address of the stack
+    #[must_use]
+    pub fn bottom(&self) -> VirtAddr {
+        self.stack.start
+    }
+
+    /// The unmapped page below the stack
+    #[must_use]
+    pub fn guard(&self) -> Region {
+        self.guard
+    }
+
+    /// Keep the stack mapped forever, returning its top.
+    ///
+    /// Used for stacks that must outlive any owner, like the ones in the TSS.
+    #[must_use]
+    pub fn leak(self) -> VirtAddr {
+        let top = self.top();
+        core::mem::forget(self);
+        top
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        if let Err(err) = vm::release(self.stack.start) {
+            error!(
+                "failed to release kernel stack at {:?}: {err:?}",
+                self.stack.start
+            );
+        }
+        if let Err(err) = vm::release(self.guard.start) {
+            error!(
+                "failed to release guard page at {:?}: {err:?}",
+                self.guard.start
+            );
+        }
+    }
+}
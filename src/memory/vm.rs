@@ -0,0 +1,543 @@
+use core::ptr;
+
+use spin::Mutex;
+use x86_64::{
+    instructions::interrupts,
+    structures::{
+        idt::PageFaultErrorCode,
+        paging::{
+            mapper::{FlagUpdateError, MapToError, UnmapError},
+            page::PageRange,
+            FrameAllocator, FrameDeallocator, Mapper, Page, PageTableFlags, PhysFrame, Size4KiB,
+            Translate,
+        },
+    },
+    PhysAddr, VirtAddr,
+};
+
+use crate::memory;
+
+const PAGE_SIZE: u64 = 4096;
+
+/// Start of the area [`reserve_anywhere`] picks regions from
+pub const KERNEL_VM_START: u64 = 0x4444_8000_0000;
+/// Exclusive end of the area [`reserve_anywhere`] picks regions from
+pub const KERNEL_VM_END: u64 = 0x4445_0000_0000;
+
+/// Maximum amount of regions that can be reserved at once
+const MAX_REGIONS: usize = 64;
+
+/// What a region is used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    Heap,
+    Stack,
+    /// Never mapped, so that running into it causes a page fault
+    Guard,
+    /// Mapped to fixed physical memory, whose frames are not freed when unmapped
+    Mmio,
+    Other,
+}
+
+/// A reserved range of virtual memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub start: VirtAddr,
+    /// Exclusive end of the region
+    pub end: VirtAddr,
+    /// Flags used to map pages in the region. Always contains `PRESENT`.
+    pub flags: PageTableFlags,
+    pub kind: RegionKind,
+    /// Whether pages are mapped on first access instead of by [`map_range`]
+    pub demand_paged: bool,
+}
+
+impl Region {
+    #[must_use]
+    pub fn contains(&self, addr: VirtAddr) -> bool {
+        self.start <= addr && addr < self.end
+    }
+
+    #[must_use]
+    pub fn size(&self) -> u64 {
+        self.end - self.start
+    }
+
+    fn contains_range(&self, start: VirtAddr, end: VirtAddr) -> bool {
+        self.start <= start && end <= self.end
+    }
+
+    fn overlaps(&self, start: VirtAddr, end: VirtAddr) -> bool {
+        self.start < end && start < self.end
+    }
+}
+
+#[derive(Debug)]
+pub enum VmError {
+    /// The range is empty or not page aligned
+    InvalidRange,
+    /// The range overlaps a reserved region
+    Overlaps,
+    /// The range is not inside a single reserved region
+    NotReserved,
+    /// The range is inside a guard region, which can never be mapped
+    GuardRegion,
+    /// [`MAX_REGIONS`] regions are already reserved
+    TableFull,
+    /// There is no free space of the requested size
+    OutOfVirtualMemory,
+    Map(MapToError<Size4KiB>),
+    Unmap(UnmapError),
+    FlagUpdate(FlagUpdateError),
+}
+
+impl From<MapToError<Size4KiB>> for VmError {
+    fn from(err: MapToError<Size4KiB>) -> Self {
+        Self::Map(err)
+    }
+}
+
+type RegionTable = [Option<Region>; MAX_REGIONS];
+
+/// Reserved regions. Uses a fixed size table, since the heap and the page fault handler depend on it.
+static REGIONS: Mutex<RegionTable> = Mutex::new([None; MAX_REGIONS]);
+
+fn with_regions<R>(f: impl FnOnce(&mut RegionTable) -> R) -> R {
+    interrupts::without_interrupts(|| f(&mut REGIONS.lock()))
+}
+
+fn check_range(start: VirtAddr, end: VirtAddr) -> Result<(), VmError> {
+    if start >= end || !start.is_aligned(PAGE_SIZE) || !end.is_aligned(PAGE_SIZE) {
+        Err(VmError::InvalidRange)
+    } else {
+        Ok(())
+    }
+}
+
+fn pages(start: VirtAddr, end: VirtAddr) -> PageRange<Size4KiB> {
+    Page::range(
+        Page::containing_address(start),
+        Page::containing_address(end),
+    )
+}
+
+fn insert(regions: &mut RegionTable, region: Region) -> Result<(), VmError> {
+    let slot = regions
+        .iter_mut()
+        .find(|r| r.is_none())
+        .ok_or(VmError::TableFull)?;
+    *slot = Some(region);
+    Ok(())
+}
+
+/// Find the region that contains the whole range
+fn find(regions: &RegionTable, start: VirtAddr, end: VirtAddr) -> Result<Region, VmError> {
+    regions
+        .iter()
+        .flatten()
+        .find(|r| r.contains_range(start, end))
+        .copied()
+        .ok_or(VmError::NotReserved)
+}
+
+/// Reserve the pages from `start` to `end` (exclusive). Nothing is mapped yet.
+///
+/// # Errors
+///
+/// See [`VmError`]
+pub fn reserve(
+    start: VirtAddr,
+    end: VirtAddr,
+    flags: PageTableFlags,
+    kind: RegionKind,
+    demand_paged: bool,
+) -> Result<Region, VmError> {
+    check_range(start, end)?;
+
+    let region = Region {
+        start,
+        end,
+        flags: flags | PageTableFlags::PRESENT,
+        kind,
+        demand_paged,
+    };
+
+    with_regions(|regions| {
+        if regions.iter().flatten().any(|r| r.overlaps(start, end)) {
+            return Err(VmError::Overlaps);
+        }
+        insert(regions, region)?;
+        Ok(region)
+    })
+}
+
+/// Find the first free range of `size` bytes between [`KERNEL_VM_START`] and [`KERNEL_VM_END`]
+fn find_free(regions: &RegionTable, size: u64) -> Result<VirtAddr, VmError> {
+    let mut start = KERNEL_VM_START;
+
+    // first fit: skip past every region in the way until nothing overlaps
+    loop {
+        let end = start
+            .checked_add(size)
+            .filter(|&end| end <= KERNEL_VM_END)
+            .ok_or(VmError::OutOfVirtualMemory)?;
+
+        let blocking = regions
+            .iter()
+            .flatten()
+            .find(|r| r.overlaps(VirtAddr::new(start), VirtAddr::new(end)));
+        match blocking {
+            Some(blocking) => start = blocking.end.as_u64(),
+            None => return Ok(VirtAddr::new(start)),
+        }
+    }
+}
+
+/// Reserve `size` bytes (rounded up to whole pages) anywhere between [`KERNEL_VM_START`] and [`KERNEL_VM_END`].
+///
+/// # Errors
+///
+/// See [`VmError`]
+pub fn reserve_anywhere(
+    size: u64,
+    flags: PageTableFlags,
+    kind: RegionKind,
+    demand_paged: bool,
+) -> Result<Region, VmError> {
+    if size == 0 {
+        return Err(VmError::InvalidRange);
+    }
+    let size = size.next_multiple_of(PAGE_SIZE);
+
+    with_regions(|regions| {
+        let start = find_free(regions, size)?;
+
+        let region = Region {
+            start,
+            end: start + size,
+            flags: flags | PageTableFlags::PRESENT,
+            kind,
+            demand_paged,
+        };
+        insert(regions, region)?;
+        Ok(region)
+    })
+}
+
+/// Like [`reserve_anywhere`], but also reserves a [`RegionKind::Guard`] page directly below the region.
+///
+/// Returns the guard region and the reserved region.
+///
+/// # Errors
+///
+/// See [`VmError`]
+pub fn reserve_guarded(
+    size: u64,
+    flags: PageTableFlags,
+    kind: RegionKind,
+) -> Result<(Region, Region), VmError> {
+    if size == 0 {
+        return Err(VmError::InvalidRange);
+    }
+    let size = size.next_multiple_of(PAGE_SIZE);
+
+    with_regions(|regions| {
+        if regions.iter().filter(|r| r.is_none()).count() < 2 {
+            return Err(VmError::TableFull);
+        }
+
+        let start = find_free(regions, PAGE_SIZE + size)?;
+
+        let guard = Region {
+            start,
+            end: start + PAGE_SIZE,
+            flags: PageTableFlags::PRESENT,
+            kind: RegionKind::Guard,
+            demand_paged: false,
+        };
+        let region = Region {
+            start: guard.end,
+            end: guard.end + size,
+            flags: flags | PageTableFlags::PRESENT,
+            kind,
+            demand_paged: false,
+        };
+        insert(regions, guard)?;
+        insert(regions, region)?;
+        Ok((guard, region))
+    })
+}
+
+/// Unmap everything in the region starting at `start` and remove it.
+///
+/// # Errors
+///
+/// Will error if no region starts at `start`, or unmapping fails.
+pub fn release(start: VirtAddr) -> Result<Region, VmError> {
+    let region = with_regions(|regions| {
+        regions
+            .iter()
+            .flatten()
+            .find(|r| r.start == start)
+            .copied()
+            .ok_or(VmError::NotReserved)
+    })?;
+
+    if region.kind != RegionKind::Guard {
+        unmap_range(region.start, region.end)?;
+    }
+
+    with_regions(|regions| {
+        if let Some(slot) = regions.iter_mut().find(|r| **r == Some(region)) {
+            *slot = None;
+        }
+    });
+
+    Ok(region)
+}
+
+/// Map fresh frames to every page from `start` to `end` (exclusive), using the flags of the region containing them.
+///
+/// If mapping fails, the pages mapped by this call are unmapped again.
+///
+/// # Errors
+///
+/// See [`VmError`]
+pub fn map_range(start: VirtAddr, end: VirtAddr) -> Result<(), VmError> {
+    check_range(start, end)?;
+    let region = with_regions(|regions| find(regions, start, end))?;
+    if region.kind == RegionKind::Guard {
+        return Err(VmError::GuardRegion);
+    }
+
+    memory::with_mapper(|mapper, frame_allocator| {
+        for (i, page) in pages(start, end).enumerate() {
+            let result = frame_allocator
+                .allocate_frame()
+                .ok_or(MapToError::FrameAllocationFailed)
+                .and_then(|frame| {
+                    unsafe { mapper.map_to(page, frame, region.flags, frame_allocator) }
+                        .inspect_err(|_| unsafe { frame_allocator.deallocate_frame(frame) })
+                });
+
+            match result {
+                Ok(flush) => flush.flush(),
+                Err(err) => {
+                    for page in pages(start, end).take(i) {
+                        if let Ok((frame, flush)) = mapper.unmap(page) {
+                            flush.flush();
+                            unsafe { frame_allocator.deallocate_frame(frame) };
+                        }
+                    }
+                    return Err(VmError::Map(err));
+                }
+            }
+        }
+
+        Ok(())
+    })
+}
+
+/// Map the pages from `start` to `start + size` to the physical memory starting at `phys`.
+///
+/// The frames are not freed when unmapped, so this is meant for [`RegionKind::Mmio`] regions.
+///
+/// # Errors
+///
+/// See [`VmError`]
+///
+/// # Safety
+///
+/// Caller must guarantee that the physical memory is not in use by anything else (such as the frame allocator).
+pub unsafe fn map_physical(start: VirtAddr, phys: PhysAddr, size: u64) -> Result<(), VmError> {
+    let end = start + size;
+    check_range(start, end)?;
+    if !phys.is_aligned(PAGE_SIZE) {
+        return Err(VmError::InvalidRange);
+    }
+
+    let region = with_regions(|regions| find(regions, start, end))?;
+    if region.kind == RegionKind::Guard {
+        return Err(VmError::GuardRegion);
+    }
+
+    memory::with_mapper(|mapper, frame_allocator| {
+        for (i, page) in pages(start, end).enumerate() {
+            let frame = PhysFrame::containing_address(phys + i as u64 * PAGE_SIZE);
+            mapper
+                .map_to(page, frame, region.flags, frame_allocator)?
+                .flush();
+        }
+        Ok(())
+    })
+}
+
+/// Unmap every mapped page from `start` to `end` (exclusive) and flush them from the TLB.
+///
+/// Frames are freed unless the region is a [`RegionKind::Mmio`] region. Pages that are not mapped are skipped.
+///
+/// # Errors
+///
+/// See [`VmError`]
+pub fn unmap_range(start: VirtAddr, end: VirtAddr) -> Result<(), VmError> {
+    check_range(start, end)?;
+    let region = with_regions(|regions| find(regions, start, end))?;
+
+    memory::with_mapper(|mapper, frame_allocator| {
+        for page in pages(start, end) {
+            match mapper.unmap(page) {
+                Ok((frame, flush)) => {
+                    flush.flush();
+                    if region.kind != RegionKind::Mmio {
+                        unsafe { frame_allocator.deallocate_frame(frame) };
+                    }
+                }
+                Err(UnmapError::PageNotMapped) => {}
+                Err(err) => return Err(VmError::Unmap(err)),
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Change the flags of the pages from `start` to `end` (exclusive).
+///
+/// If the range is only part of a region, the region is split so later mappings in the rest keep the old flags.
+///
+/// # Errors
+///
+/// See [`VmError`]
+pub fn protect(start: VirtAddr, end: VirtAddr, flags: PageTableFlags) -> Result<(), VmError> {
+    check_range(start, end)?;
+    let flags = flags | PageTableFlags::PRESENT;
+
+    with_regions(|regions| {
+        let index = regions
+            .iter()
+            .position(|r| r.is_some_and(|r| r.contains_range(start, end)))
+            .ok_or(VmError::NotReserved)?;
+        let Some(region) = regions[index] else {
+            return Err(VmError::NotReserved);
+        };
+
+        let needed = usize::from(region.start < start) + usize::from(end < region.end);
+        if regions.iter().filter(|r| r.is_none()).count() < needed {
+            return Err(VmError::TableFull);
+        }
+
+        regions[index] = Some(Region {
+            start,
+            end,
+            flags,
+            ..region
+        });
+        if region.start < start {
+            insert(
+                regions,
+                Region {
+                    end: start,
+                    ..region
+                },
+            )?;
+        }
+        if end < region.end {
+            insert(
+                regions,
+                Region {
+                    start: end,
+                    ..region
+                },
+            )?;
+        }
+
+        Ok(())
+    })?;
+
+    memory::with_mapper(|mapper, _| {
+        for page in pages(start, end) {
+            match unsafe { mapper.update_flags(page, flags) } {
+                Ok(flush) => flush.flush(),
+                Err(FlagUpdateError::PageNotMapped) => {}
+                Err(err) => return Err(VmError::FlagUpdate(err)),
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Get the region containing `addr`
+#[must_use]
+pub fn region_at(addr: VirtAddr) -> Option<Region> {
+    with_regions(|regions| regions.iter().flatten().find(|r| r.contains(addr)).copied())
+}
+
+/// Like [`region_at`], but returns `None` instead of waiting if the region table is locked.
+///
+/// Meant for exception handlers, which may have interrupted code holding the lock.
+pub(crate) fn try_region_at(addr: VirtAddr) -> Option<Region> {
+    REGIONS
+        .try_lock()
+        .and_then(|regions| regions.iter().flatten().find(|r| r.contains(addr)).copied())
+}
+
+/// Get every reserved region
+pub fn regions() -> impl Iterator<Item = Region> {
+    let regions = with_regions(|regions| *regions);
+    regions.into_iter().flatten()
+}
+
+/// Get the physical address `addr` is mapped to, if it is mapped
+#[must_use]
+pub fn translate(addr: VirtAddr) -> Option<PhysAddr> {
+    memory::with_mapper(|mapper, _| mapper.translate_addr(addr))
+}
+
+/// Try to resolve a page fault at `addr` by mapping a zeroed frame in a demand paged region.
+/// Called by the page fault handler.
+///
+/// Returns whether the fault was resolved.
+pub(crate) fn handle_page_fault(addr: VirtAddr, error_code: PageFaultErrorCode) -> bool {
+    // the page is present, so this is an access the flags do not allow
+    if error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
+        return false;
+    }
+
+    // the faulting code may hold the lock, in which case the fault is not ours to resolve
+    let Some(region) = try_region_at(addr) else {
+        return false;
+    };
+
+    if !region.demand_paged || region.kind == RegionKind::Guard {
+        return false;
+    }
+    if error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE)
+        && !region.flags.contains(PageTableFlags::WRITABLE)
+    {
+        return false;
+    }
+    if error_code.contains(PageFaultErrorCode::USER_MODE)
+        && !region.flags.contains(PageTableFlags::USER_ACCESSIBLE)
+    {
+        return false;
+    }
+
+    let page: Page<Size4KiB> = Page::containing_address(addr);
+
+    let mapped = memory::try_with_mapper(|mapper, frame_allocator| {
+        let frame = frame_allocator.allocate_frame()?;
+
+        // zero through the physical memory mapping, since the page itself might not be writable
+        let frame_ptr: *mut u8 = memory::phys_to_virt(frame.start_address()).as_mut_ptr();
+        unsafe { ptr::write_bytes(frame_ptr, 0, 4096) };
+
+        if let Ok(flush) = unsafe { mapper.map_to(page, frame, region.flags, frame_allocator) } {
+            flush.flush();
+            Some(())
+        } else {
+            unsafe { frame_allocator.deallocate_frame(frame) };
+            None
+        }
+    });
+
+    mapped.flatten().is_some()
+}
@@ -0,0 +1,257 @@
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use log::{trace, warn};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use super::Ps2Error;
+
+const SET_LEDS: u8 = 0xed;
+const SET_TYPEMATIC: u8 = 0xf3;
+const ENABLE_SCANNING: u8 = 0xf4;
+
+const ACK: u8 = 0xfa;
+const RESEND: u8 = 0xfe;
+
+/// How many times a byte is resent before the command is given up on
+const MAX_RESENDS: u8 = 3;
+
+/// The delay before a held key starts repeating, and how many times per second it repeats
+pub const DEFAULT_TYPEMATIC: (Duration, u32) = (Duration::from_millis(500), 20);
+
+/// Set once [`init`] succeeded, commands are not sent to a keyboard that may not exist before that
+static INITIALISED: AtomicBool = AtomicBool::new(false);
+
+/// The lock key LEDs on the keyboard
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Leds {
+    pub scroll_lock: bool,
+    pub num_lock: bool,
+    pub caps_lock: bool,
+}
+
+impl Leds {
+    fn bits(self) -> u8 {
+        u8::from(self.scroll_lock) | u8::from(self.num_lock) << 1 | u8::from(self.caps_lock) << 2
+    }
+}
+
+/// A command with its argument byte
+#[derive(Debug, Clone, Copy)]
+struct Command {
+    bytes: [u8; 2],
+    /// How many bytes were acknowledged
+    acked: usize,
+    resends: u8,
+}
+
+impl Command {
+    fn new(command: u8, argument: u8) -> Self {
+        Self {
+            bytes: [command, argument],
+            acked: 0,
+            resends: 0,
+        }
+    }
+}
+
+/// Commands waiting for the keyboard to acknowledge them.
+///
+/// Only the latest command of each kind is kept, since older ones would be overwritten anyway.
+struct Commands {
+    in_flight: Option<Command>,
+    leds: Option<u8>,
+    typematic: Option<u8>,
+}
+
+impl Commands {
+    /// Send the next queued command, if none is waiting for a response
+    fn start_next(&mut self) {
+        if self.in_flight.is_some() {
+            return;
+        }
+
+        let command = if let Some(leds) = self.leds.take() {
+            Command::new(SET_LEDS, leds)
+        } else if let Some(typematic) = self.typematic.take() {
+            Command::new(SET_TYPEMATIC, typematic)
+        } else {
+            return;
+        };
+
+        self.in_flight = Some(command);
+        self.send_current();
+    }
+
+    fn send_current(&mut self) {
+        let Some(command) = &self.in_flight else {
+            return;
+        };
+
+        if super::write_data(command.bytes[command.acked]).is_err() {
+            warn!("keyboard controller timed out, dropping command {command:?}");
+            self.in_flight = None;
+        }
+    }
+
+    fn acked(&mut self) {
+        let Some(command) = &mut self.in_flight else {
+            return;
+        };
+
+        command.acked += 1;
+        command.resends = 0;
+        if command.acked == command.bytes.len() {
+            self.in_flight = None;
+            self.start_next();
+        } else {
+            self.send_current();
+        }
+    }
+
+    fn resend(&mut self) {
+        let Some(command) = &mut self.in_flight else {
+            return;
+        };
+
+        command.resends += 1;
+        if command.resends > MAX_RESENDS {
+            warn!("keyboard kept asking for resends, dropping command {command:?}");
+            self.in_flight = None;
+            self.start_next();
+        } else {
+            self.send_current();
+        }
+    }
+}
+
+/// Locked with interrupts disabled, since the keyboard interrupt locks it too
+static COMMANDS: Mutex<Commands> = Mutex::new(Commands {
+    in_flight: None,
+    leds: None,
+    typematic: None,
+});
+
+/// Set up the keyboard by polling, while its interrupt is still disabled
+pub(super) fn init() -> Result<(), Ps2Error> {
+    trace!("initialising ps/2 keyboard");
+    let (delay, rate) = DEFAULT_TYPEMATIC;
+    send_polled(SET_TYPEMATIC, Some(typematic_byte(delay, rate)))?;
+    // the keyboard starts with num lock on
+    let leds = Leds {
+        num_lock: true,
+        ..Leds::default()
+    };
+    send_polled(SET_LEDS, Some(leds.bits()))?;
+    send_polled(ENABLE_SCANNING, None)?;
+
+    INITIALISED.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Send a command and wait for it to be acknowledged, resending bytes when asked to
+fn send_polled(command: u8, argument: Option<u8>) -> Result<(), Ps2Error> {
+    for byte in core::iter::once(command).chain(argument) {
+        let mut resends = 0;
+        loop {
+            super::write_data(byte)?;
+            match super::read_data()? {
+                ACK => break,
+                RESEND if resends < MAX_RESENDS => resends += 1,
+                RESEND => return Err(Ps2Error::TooManyResends),
+                response => return Err(Ps2Error::UnexpectedResponse(response)),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Turn the lock key LEDs on or off
+pub fn set_leds(leds: Leds) {
+    queue(|commands| commands.leds = Some(leds.bits()));
+}
+
+/// Set how long a key must be held before it starts repeating, and how many times per second it then repeats.
+///
+/// The keyboard only supports delays of 250 to 1000ms and rates of 2 to 30 repeats per second,
+/// so the closest supported values are used.
+pub fn set_typematic(delay: Duration, repeats_per_sec: u32) {
+    let byte = typematic_byte(delay, repeats_per_sec);
+    queue(|commands| commands.typematic = Some(byte));
+}
+
+fn queue(f: impl FnOnce(&mut Commands)) {
+    if !INITIALISED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    interrupts::without_interrupts(|| {
+        let mut commands = COMMANDS.lock();
+        f(&mut commands);
+        commands.start_next();
+    });
+}
+
+/// Called by the keyboard interrupt handler with every byte it reads.
+///
+/// Returns whether the byte was a response to a command, instead of a scancode.
+pub(crate) fn handle_byte(byte: u8) -> bool {
+    if byte != ACK && byte != RESEND {
+        return false;
+    }
+
+    // the byte is never a scancode, so it is consumed even if the commands are somehow locked
+    if let Some(mut commands) = COMMANDS.try_lock() {
+        if byte == ACK {
+            commands.acked();
+        } else {
+            commands.resend();
+        }
+    }
+
+    true
+}
+
+/// Encode `delay` and `repeats_per_sec` into the argument of the set typematic command
+fn typematic_byte(delay: Duration, repeats_per_sec: u32) -> u8 {
+    // 0 is 250ms, up to 3 for 1000ms
+    let delay_bits = delay.as_millis().clamp(250, 1000).div_ceil(250) - 1;
+    #[allow(clippy::cast_possible_truncation, reason = "clamped to 0..=3")]
+    let delay_bits = delay_bits as u8;
+
+    // the repeat period is (8 + a) * 2^b * 4.17ms, with a in bits 0..3 and b in bits 3..5
+    let wanted_period = 1_000_000 / u64::from(repeats_per_sec.max(1));
+    let rate_bits = (0..32u8)
+        .min_by_key(|&bits| {
+            let period = (8 + u64::from(bits & 0b111)) * (1 << (bits >> 3)) * 4170;
+            period.abs_diff(wanted_period)
+        })
+        .unwrap_or(0);
+
+    delay_bits << 5 | rate_bits
+}
+
+#[test_case]
+fn test_typematic_byte() {
+    // fastest and slowest
+    assert_eq!(typematic_byte(Duration::from_millis(250), 30), 0x00);
+    assert_eq!(typematic_byte(Duration::from_secs(1), 2), 0x7f);
+    // out of range values are clamped
+    assert_eq!(typematic_byte(Duration::ZERO, 1000), 0x00);
+    // 10.9 repeats per second, with a 500ms delay
+    assert_eq!(typematic_byte(Duration::from_millis(500), 11), 0x20 | 0x0b);
+}
+
+#[test_case]
+fn test_led_bits() {
+    let leds = Leds {
+        scroll_lock: true,
+        num_lock: false,
+        caps_lock: true,
+    };
+    assert_eq!(leds.bits(), 0b101);
+}
@@ -0,0 +1,190 @@
+//! Driver for the 8042 PS/2 controller, which the keyboard and mouse are connected to
+
+pub mod keyboard;
+pub mod mouse;
+
+use log::{trace, warn};
+use x86_64::instructions::{interrupts, port::Port};
+
+use crate::interrupt::{self, InterruptIndex};
+
+const DATA_PORT: u16 = 0x60;
+/// Status register when read, command register when written
+const COMMAND_PORT: u16 = 0x64;
+
+/// Status bit set when there is a byte to read from the data port
+const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+/// Status bit set while the controller has not consumed the last byte written to it
+const STATUS_INPUT_FULL: u8 = 1 << 1;
+
+const READ_CONFIG: u8 = 0x20;
+const WRITE_CONFIG: u8 = 0x60;
+const DISABLE_SECOND_PORT: u8 = 0xa7;
+const DISABLE_FIRST_PORT: u8 = 0xad;
+const ENABLE_FIRST_PORT: u8 = 0xae;
+const ENABLE_SECOND_PORT: u8 = 0xa8;
+const TEST_CONTROLLER: u8 = 0xaa;
+const TEST_FIRST_PORT: u8 = 0xab;
+const TEST_SECOND_PORT: u8 = 0xa9;
+/// Send the next byte written to the data port to the mouse instead of the keyboard
+const WRITE_SECOND_PORT: u8 = 0xd4;
+
+const CONFIG_FIRST_IRQ: u8 = 1 << 0;
+const CONFIG_SECOND_IRQ: u8 = 1 << 1;
+const CONFIG_FIRST_CLOCK_DISABLED: u8 = 1 << 4;
+const CONFIG_SECOND_CLOCK_DISABLED: u8 = 1 << 5;
+
+const SELF_TEST_PASSED: u8 = 0x55;
+const PORT_TEST_PASSED: u8 = 0x00;
+
+/// How many times to poll the status register before giving up on the controller
+const TIMEOUT: u32 = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ps2Error {
+    /// The controller or device did not respond in time, or there is no controller
+    Timeout,
+    /// The controller's self test returned this instead of `0x55`
+    SelfTestFailed(u8),
+    /// Testing the keyboard's port returned this error code
+    PortTestFailed(u8),
+    /// The device kept asking for a byte to be resent
+    TooManyResends,
+    /// The device responded with this instead of acknowledging a command
+    UnexpectedResponse(u8),
+}
+
+/// Initialise and self test the controller, then set up the keyboard and the mouse if there is one.
+///
+/// Interrupts are disabled while the controller is being set up.
+///
+/// # Errors
+///
+/// Will error if the controller or keyboard fail a test or stop responding. See [`Ps2Error`]
+pub fn init() -> Result<(), Ps2Error> {
+    trace!("initialising ps/2 controller");
+
+    interrupts::without_interrupts(|| {
+        // nothing may send us bytes while we are talking to the controller
+        command(DISABLE_FIRST_PORT)?;
+        command(DISABLE_SECOND_PORT)?;
+        flush();
+
+        // keep scancode translation on, the keyboard driver expects set 1
+        let mut config = command_with_response(READ_CONFIG)?;
+        config &= !(CONFIG_FIRST_IRQ | CONFIG_SECOND_IRQ);
+        write_config(config)?;
+
+        let result = command_with_response(TEST_CONTROLLER)?;
+        if result != SELF_TEST_PASSED {
+            return Err(Ps2Error::SelfTestFailed(result));
+        }
+        // some controllers reset themselves during the self test
+        write_config(config)?;
+
+        let result = command_with_response(TEST_FIRST_PORT)?;
+        if result != PORT_TEST_PASSED {
+            return Err(Ps2Error::PortTestFailed(result));
+        }
+
+        // the mouse is optional, so failing to set it up is not an error
+        let has_mouse = command_with_response(TEST_SECOND_PORT)? == PORT_TEST_PASSED;
+        let mouse = if has_mouse {
+            command(ENABLE_SECOND_PORT)?;
+            let mouse = mouse::init();
+            if let Err(err) = mouse {
+                warn!("failed to initialise ps/2 mouse: {err:?}");
+                command(DISABLE_SECOND_PORT)?;
+            }
+            mouse.is_ok()
+        } else {
+            false
+        };
+
+        command(ENABLE_FIRST_PORT)?;
+        keyboard::init()?;
+
+        // enabling the ports cleared their clock disable bits, which must stay clear
+        config &= !(CONFIG_FIRST_CLOCK_DISABLED | CONFIG_SECOND_CLOCK_DISABLED);
+        config |= CONFIG_FIRST_IRQ;
+        if mouse {
+            config |= CONFIG_SECOND_IRQ;
+        } else {
+            config |= CONFIG_SECOND_CLOCK_DISABLED;
+        }
+        write_config(config)?;
+
+        if mouse {
+            interrupt::enable_irq(InterruptIndex::Mouse);
+        }
+        Ok(())
+    })
+}
+
+fn status() -> u8 {
+    unsafe { Port::new(COMMAND_PORT).read() }
+}
+
+/// Wait until the controller can take another byte
+fn wait_for_input() -> Result<(), Ps2Error> {
+    for _ in 0..TIMEOUT {
+        if status() & STATUS_INPUT_FULL == 0 {
+            return Ok(());
+        }
+    }
+    Err(Ps2Error::Timeout)
+}
+
+/// Wait until there is a byte to read
+fn wait_for_output() -> Result<(), Ps2Error> {
+    for _ in 0..TIMEOUT {
+        if status() & STATUS_OUTPUT_FULL != 0 {
+            return Ok(());
+        }
+    }
+    Err(Ps2Error::Timeout)
+}
+
+/// Throw away any bytes the controller has buffered
+fn flush() {
+    while status() & STATUS_OUTPUT_FULL != 0 {
+        let _: u8 = unsafe { Port::new(DATA_PORT).read() };
+    }
+}
+
+fn command(command: u8) -> Result<(), Ps2Error> {
+    wait_for_input()?;
+    unsafe { Port::new(COMMAND_PORT).write(command) };
+    Ok(())
+}
+
+fn command_with_response(command: u8) -> Result<u8, Ps2Error> {
+    self::command(command)?;
+    read_data()
+}
+
+fn write_config(config: u8) -> Result<(), Ps2Error> {
+    command(WRITE_CONFIG)?;
+    write_data(config)
+}
+
+/// Poll for a byte from the controller, keyboard or mouse.
+///
+/// Only usable while the devices' interrupts are disabled, otherwise the interrupt handlers take the byte.
+fn read_data() -> Result<u8, Ps2Error> {
+    wait_for_output()?;
+    Ok(unsafe { Port::new(DATA_PORT).read() })
+}
+
+/// Send a byte to the mouse
+fn write_second_port(byte: u8) -> Result<(), Ps2Error> {
+    command(WRITE_SECOND_PORT)?;
+    write_data(byte)
+}
+
+/// Send a byte to the controller, or to the keyboard if no controller command is expecting it
+fn write_data(byte: u8) -> Result<(), Ps2Error> {
+    wait_for_input()?;
+    unsafe { Port::new(DATA_PORT).write(byte) };
+    Ok(())
+}
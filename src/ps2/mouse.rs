@@ -0,0 +1,189 @@
+use log::trace;
+use spin::Mutex;
+
+use super::Ps2Error;
+
+const SET_SAMPLE_RATE: u8 = 0xf3;
+const GET_DEVICE_ID: u8 = 0xf2;
+const ENABLE_REPORTING: u8 = 0xf4;
+const SET_DEFAULTS: u8 = 0xf6;
+
+const ACK: u8 = 0xfa;
+const RESEND: u8 = 0xfe;
+const MAX_RESENDS: u8 = 3;
+
+/// Device id of mice with a scroll wheel, once it was unlocked
+const WHEEL_MOUSE_ID: u8 = 3;
+
+/// Bit of the first packet byte that is always set, used to find the start of a packet
+const ALWAYS_ONE: u8 = 1 << 3;
+const X_SIGN: u8 = 1 << 4;
+const Y_SIGN: u8 = 1 << 5;
+const X_OVERFLOW: u8 = 1 << 6;
+const Y_OVERFLOW: u8 = 1 << 7;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MouseButtons {
+    pub left: bool,
+    pub right: bool,
+    pub middle: bool,
+}
+
+/// Movement and button state from one packet
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MouseEvent {
+    /// Movement to the right since the last packet
+    pub dx: i16,
+    /// Movement up since the last packet
+    pub dy: i16,
+    /// Scroll wheel movement, positive is towards the user. Always 0 without a scroll wheel.
+    pub scroll: i8,
+    /// Which buttons are held
+    pub buttons: MouseButtons,
+}
+
+/// Assembles bytes from the mouse into packets
+pub struct PacketDecoder {
+    bytes: [u8; 4],
+    len: usize,
+    /// 3, or 4 for mice with a scroll wheel
+    packet_size: usize,
+}
+
+impl PacketDecoder {
+    #[must_use]
+    pub const fn new(has_wheel: bool) -> Self {
+        Self {
+            bytes: [0; 4],
+            len: 0,
+            packet_size: if has_wheel { 4 } else { 3 },
+        }
+    }
+
+    /// Feed one byte, returning an event once a whole packet was received
+    pub fn add_byte(&mut self, byte: u8) -> Option<MouseEvent> {
+        // out of sync, wait for something that can be the start of a packet
+        if self.len == 0 && byte & ALWAYS_ONE == 0 {
+            return None;
+        }
+
+        self.bytes[self.len] = byte;
+        self.len += 1;
+        if self.len < self.packet_size {
+            return None;
+        }
+        self.len = 0;
+
+        Some(self.decode())
+    }
+
+    fn decode(&self) -> MouseEvent {
+        let [flags, x, y, z] = self.bytes;
+
+        // 9 bit two's complement, with the sign bit in the flags
+        let movement = |value: u8, sign: u8, overflow: u8| {
+            if flags & overflow != 0 {
+                0
+            } else if flags & sign != 0 {
+                i16::from(value) - 0x100
+            } else {
+                i16::from(value)
+            }
+        };
+
+        let scroll = if self.packet_size == 4 {
+            // 4 bit two's complement
+            #[allow(clippy::cast_possible_wrap, reason = "sign extending on purpose")]
+            let z = (z << 4) as i8 >> 4;
+            z
+        } else {
+            0
+        };
+
+        MouseEvent {
+            dx: movement(x, X_SIGN, X_OVERFLOW),
+            dy: movement(y, Y_SIGN, Y_OVERFLOW),
+            scroll,
+            buttons: MouseButtons {
+                left: flags & 1 != 0,
+                right: flags & 1 << 1 != 0,
+                middle: flags & 1 << 2 != 0,
+            },
+        }
+    }
+}
+
+/// Only locked by the mouse interrupt, and by [`init`] before the interrupt is enabled
+static DECODER: Mutex<PacketDecoder> = Mutex::new(PacketDecoder::new(false));
+
+/// Set up the mouse by polling, while its interrupt is still disabled.
+///
+/// Returns whether the mouse has a scroll wheel.
+pub(super) fn init() -> Result<bool, Ps2Error> {
+    trace!("initialising ps/2 mouse");
+    send(SET_DEFAULTS)?;
+
+    // the magic sequence that makes wheel mice report scroll movement
+    for rate in [200, 100, 80] {
+        send(SET_SAMPLE_RATE)?;
+        send(rate)?;
+    }
+    send(GET_DEVICE_ID)?;
+    let has_wheel = super::read_data()? == WHEEL_MOUSE_ID;
+
+    send(ENABLE_REPORTING)?;
+
+    *DECODER.lock() = PacketDecoder::new(has_wheel);
+    Ok(has_wheel)
+}
+
+/// Send a byte to the mouse and wait for it to be acknowledged
+fn send(byte: u8) -> Result<(), Ps2Error> {
+    for _ in 0..=MAX_RESENDS {
+        super::write_second_port(byte)?;
+        match super::read_data()? {
+            ACK => return Ok(()),
+            RESEND => {}
+            response => return Err(Ps2Error::UnexpectedResponse(response)),
+        }
+    }
+
+    Err(Ps2Error::TooManyResends)
+}
+
+/// Called by the mouse interrupt handler with every byte it reads
+pub(crate) fn handle_byte(byte: u8) -> Option<MouseEvent> {
+    DECODER.try_lock()?.add_byte(byte)
+}
+
+#[test_case]
+fn test_decode_packet() {
+    let mut decoder = PacketDecoder::new(false);
+
+    // left button, moved right 5 and down 3
+    assert_eq!(decoder.add_byte(0b0010_1001), None);
+    assert_eq!(decoder.add_byte(5), None);
+    let event = decoder.add_byte(0xfd).expect("packet not decoded");
+    assert_eq!((event.dx, event.dy), (5, -3));
+    assert!(event.buttons.left && !event.buttons.right);
+
+    // bytes without the always set bit are skipped until a packet starts
+    assert_eq!(decoder.add_byte(0), None);
+    decoder.add_byte(0b1000_1000);
+    decoder.add_byte(0xff);
+    let event = decoder.add_byte(0).expect("packet not decoded");
+    // overflowed movement is dropped
+    assert_eq!((event.dx, event.dy), (255, 0));
+}
+
+#[test_case]
+fn test_decode_wheel_packet() {
+    let mut decoder = PacketDecoder::new(true);
+
+    for byte in [0b0000_1100, 0, 0] {
+        assert_eq!(decoder.add_byte(byte), None);
+    }
+    let event = decoder.add_byte(0x0f).expect("packet not decoded");
+    assert_eq!(event.scroll, -1);
+    assert!(event.buttons.middle);
+}
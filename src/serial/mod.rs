@@ -0,0 +1,227 @@
+//! The COM1 to COM4 serial ports.
+//!
+//! The ports are detected the first time one is used. [`serial_print!`] output, like test results, goes to the
+//! log port, which is COM1 unless changed with [`set_log_port`].
+
+#![allow(clippy::module_name_repetitions)]
+
+mod uart;
+
+use core::{
+    fmt::{self, Write},
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use conquer_once::spin::Lazy;
+use log::trace;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::interrupt::{self, InterruptIndex};
+
+pub use uart::{Config, DataBits, Parity, StopBits, Uart};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComPort {
+    Com1,
+    Com2,
+    Com3,
+    Com4,
+}
+
+impl ComPort {
+    pub const ALL: [Self; 4] = [Self::Com1, Self::Com2, Self::Com3, Self::Com4];
+
+    /// The first of the port's I/O ports
+    #[must_use]
+    pub const fn base(self) -> u16 {
+        match self {
+            Self::Com1 => 0x3f8,
+            Self::Com2 => 0x2f8,
+            Self::Com3 => 0x3e8,
+            Self::Com4 => 0x2e8,
+        }
+    }
+
+    /// The interrupt raised when the port received bytes, which COM1 and COM3 share, as do COM2 and COM4
+    #[must_use]
+    pub const fn interrupt(self) -> InterruptIndex {
+        match self {
+            Self::Com1 | Self::Com3 => InterruptIndex::Com1,
+            Self::Com2 | Self::Com4 => InterruptIndex::Com2,
+        }
+    }
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Com1 => "COM1",
+            Self::Com2 => "COM2",
+            Self::Com3 => "COM3",
+            Self::Com4 => "COM4",
+        }
+    }
+
+    /// Index into [`ComPort::ALL`]
+    #[must_use]
+    pub const fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerialError {
+    /// The baud rate does not divide 115200 evenly, or is below 2 baud
+    InvalidBaudRate(u32),
+    /// The port did not receive the byte it sent to itself, so it most likely does not exist
+    LoopbackFailed,
+    /// The port was not detected
+    NotPresent(ComPort),
+}
+
+/// Every port, `None` if it was not detected.
+///
+/// Only locked with interrupts disabled, since the serial interrupts lock them too.
+static PORTS: Lazy<[Mutex<Option<Uart>>; 4]> = Lazy::new(|| {
+    ComPort::ALL.map(|port| {
+        let uart = unsafe { Uart::init(port.base(), Config::DEFAULT) };
+        Mutex::new(uart.ok())
+    })
+});
+
+/// Index of the port [`serial_print!`] writes to, or [`NO_PORT`]
+static LOG_PORT: AtomicU8 = AtomicU8::new(ComPort::Com1 as u8);
+const NO_PORT: u8 = u8::MAX;
+
+/// Detect the ports, and unmask the interrupts of the ones that exist so that received bytes reach their
+/// [`SerialStream`](crate::task::serial::SerialStream)s
+pub fn init() {
+    for port in ComPort::ALL {
+        if let Some(config) = config(port) {
+            trace!("found {}: {config:?}", port.name());
+            interrupt::enable_irq(port.interrupt());
+        }
+    }
+}
+
+/// Run `f` on `port`, with interrupts disabled.
+///
+/// # Errors
+///
+/// Will error if `port` was not detected.
+pub fn with_port<R>(port: ComPort, f: impl FnOnce(&mut Uart) -> R) -> Result<R, SerialError> {
+    interrupts::without_interrupts(|| {
+        let mut uart = PORTS[port.index()].lock();
+        let uart = uart.as_mut().ok_or(SerialError::NotPresent(port))?;
+        Ok(f(uart))
+    })
+}
+
+/// The line settings of `port`, or `None` if it was not detected
+#[must_use]
+pub fn config(port: ComPort) -> Option<Config> {
+    with_port(port, |uart| uart.config()).ok()
+}
+
+/// Change the line settings of `port`, which also detects it again
+///
+/// # Errors
+///
+/// Will error if the baud rate can not be used, in which case the port is left unchanged,
+/// or if the port does not exist. See [`SerialError`]
+pub fn configure(port: ComPort, config: Config) -> Result<(), SerialError> {
+    interrupts::without_interrupts(|| {
+        let mut uart = PORTS[port.index()].lock();
+        match unsafe { Uart::init(port.base(), config) } {
+            Ok(new) => {
+                let was_present = uart.replace(new).is_some();
+                if !was_present {
+                    interrupt::enable_irq(port.interrupt());
+                }
+                Ok(())
+            }
+            Err(err @ SerialError::LoopbackFailed) => {
+                *uart = None;
+                Err(err)
+            }
+            Err(err) => Err(err),
+        }
+    })
+}
+
+/// Send [`serial_print!`] output to `port`, or nowhere if `None`
+///
+/// # Errors
+///
+/// Will error if `port` was not detected, in which case the log port is left unchanged.
+pub fn set_log_port(port: Option<ComPort>) -> Result<(), SerialError> {
+    let index = match port {
+        Some(port) => {
+            with_port(port, |_| ())?;
+            #[allow(clippy::cast_possible_truncation, reason = "there are 4 ports")]
+            let index = port.index() as u8;
+            index
+        }
+        None => NO_PORT,
+    };
+
+    LOG_PORT.store(index, Ordering::Relaxed);
+    Ok(())
+}
+
+/// The port [`serial_print!`] writes to
+#[must_use]
+pub fn log_port() -> Option<ComPort> {
+    ComPort::ALL
+        .get(usize::from(LOG_PORT.load(Ordering::Relaxed)))
+        .copied()
+}
+
+/// Called by the serial interrupt handlers, passing bytes received on the ports that raise `interrupt` to their
+/// [`SerialStream`](crate::task::serial::SerialStream)s
+pub(crate) fn handle_interrupt(interrupt: InterruptIndex) {
+    for port in ComPort::ALL {
+        if port.interrupt() != interrupt {
+            continue;
+        }
+        // the uart only raises another interrupt once everything it received was read
+        let Some(mut uart) = PORTS[port.index()].try_lock() else {
+            continue;
+        };
+        let Some(uart) = uart.as_mut() else {
+            continue;
+        };
+        while let Some(byte) = uart.try_receive() {
+            crate::task::serial::add_byte(port, byte);
+        }
+    }
+}
+
+#[macro_export]
+macro_rules! serial_print {
+    ($($arg:tt)*) => ($crate::serial::private_print(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! serial_println {
+    () => ($crate::serial_print!("\n"));
+    ($($arg:tt)*) => ($crate::serial_print!("{}\n", format_args!($($arg)*)));
+}
+
+#[doc(hidden)]
+pub fn private_print(args: fmt::Arguments) {
+    let Some(port) = log_port() else {
+        return;
+    };
+    // if the log port went missing there is nowhere to report that
+    let _ = with_port(port, |uart| uart.write_fmt(args));
+}
+
+#[test_case]
+fn test_log_port() {
+    assert_eq!(log_port(), Some(ComPort::Com1));
+    set_log_port(None).expect("failed to disable log port");
+    assert_eq!(log_port(), None);
+    set_log_port(Some(ComPort::Com1)).expect("COM1 not detected");
+    assert_eq!(log_port(), Some(ComPort::Com1));
+}
@@ -0,0 +1,283 @@
+use core::fmt;
+
+use x86_64::instructions::port::Port;
+
+use super::SerialError;
+
+/// Data register, or the low byte of the divisor while [`LINE_DLAB`] is set
+const DATA: u16 = 0;
+/// Interrupt enable register, or the high byte of the divisor while [`LINE_DLAB`] is set
+const INTERRUPT_ENABLE: u16 = 1;
+const FIFO_CONTROL: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+
+/// Makes the first two registers access the baud rate divisor
+const LINE_DLAB: u8 = 1 << 7;
+const LINE_TWO_STOP_BITS: u8 = 1 << 2;
+
+/// Enable and clear the fifos, raising the receive interrupt once 14 bytes are waiting
+const FIFO_ENABLE_CLEAR_14: u8 = 0xc7;
+
+const MODEM_DTR: u8 = 1 << 0;
+const MODEM_RTS: u8 = 1 << 1;
+const MODEM_OUT1: u8 = 1 << 2;
+/// Connects the uart's interrupt to the PIC
+const MODEM_OUT2: u8 = 1 << 3;
+/// Feeds everything sent back into the receiver, without sending it on the line
+const MODEM_LOOPBACK: u8 = 1 << 4;
+
+const INTERRUPT_RECEIVED: u8 = 1 << 0;
+
+/// Line status bit set when there is a received byte to read
+const STATUS_DATA_READY: u8 = 1 << 0;
+/// Line status bit set when another byte can be sent
+const STATUS_TRANSMIT_EMPTY: u8 = 1 << 5;
+
+/// The uart's clock, baud rates are this divided by a 16 bit divisor
+const MAX_BAUD_RATE: u32 = 115_200;
+
+/// Sent and expected back while the port is in loopback mode
+const LOOPBACK_TEST_BYTE: u8 = 0xae;
+/// How many times to poll for the loopback byte before deciding there is no port
+const LOOPBACK_TIMEOUT: u32 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataBits {
+    Five,
+    Six,
+    Seven,
+    Eight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+    /// The parity bit is always 1
+    Mark,
+    /// The parity bit is always 0
+    Space,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopBits {
+    One,
+    /// 1.5 stop bits with [`DataBits::Five`]
+    Two,
+}
+
+/// Line settings of a serial port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    /// Must divide 115200 evenly
+    pub baud_rate: u32,
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+impl Config {
+    /// 38400 baud, 8 data bits, no parity and 1 stop bit
+    pub const DEFAULT: Self = Self {
+        baud_rate: 38400,
+        data_bits: DataBits::Eight,
+        parity: Parity::None,
+        stop_bits: StopBits::One,
+    };
+
+    /// The value of the line control register for these settings
+    fn line_control(self) -> u8 {
+        let data_bits = match self.data_bits {
+            DataBits::Five => 0b00,
+            DataBits::Six => 0b01,
+            DataBits::Seven => 0b10,
+            DataBits::Eight => 0b11,
+        };
+        let parity = match self.parity {
+            Parity::None => 0b000,
+            Parity::Odd => 0b001,
+            Parity::Even => 0b011,
+            Parity::Mark => 0b101,
+            Parity::Space => 0b111,
+        };
+        let stop_bits = match self.stop_bits {
+            StopBits::One => 0,
+            StopBits::Two => LINE_TWO_STOP_BITS,
+        };
+
+        data_bits | stop_bits | parity << 3
+    }
+
+    /// The baud rate divisor for these settings
+    fn divisor(self) -> Result<u16, SerialError> {
+        let invalid = SerialError::InvalidBaudRate(self.baud_rate);
+        // also rejects 0
+        if !MAX_BAUD_RATE.is_multiple_of(self.baud_rate) {
+            return Err(invalid);
+        }
+        u16::try_from(MAX_BAUD_RATE / self.baud_rate).map_err(|_| invalid)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// In the usual short form, like `38400 8N1`
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let data_bits = match self.data_bits {
+            DataBits::Five => 5,
+            DataBits::Six => 6,
+            DataBits::Seven => 7,
+            DataBits::Eight => 8,
+        };
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Odd => 'O',
+            Parity::Even => 'E',
+            Parity::Mark => 'M',
+            Parity::Space => 'S',
+        };
+        let stop_bits = match self.stop_bits {
+            StopBits::One => 1,
+            StopBits::Two => 2,
+        };
+
+        write!(f, "{} {data_bits}{parity}{stop_bits}", self.baud_rate)
+    }
+}
+
+/// A 16550 compatible uart
+#[derive(Debug)]
+pub struct Uart {
+    base: u16,
+    config: Config,
+}
+
+impl Uart {
+    /// Set up the uart at `base` with `config` and check that it works by sending a byte to itself.
+    ///
+    /// Leaves the receive interrupt enabled.
+    ///
+    /// # Safety
+    ///
+    /// `base` must be the base port of a uart or unused, and nothing else may be using the uart.
+    ///
+    /// # Errors
+    ///
+    /// Will error if `config` has a baud rate the uart can not use, or if the loopback test failed,
+    /// which usually means there is no uart at `base`.
+    pub unsafe fn init(base: u16, config: Config) -> Result<Self, SerialError> {
+        let divisor = config.divisor()?;
+        let mut uart = Self { base, config };
+
+        unsafe {
+            uart.write(INTERRUPT_ENABLE, 0);
+
+            let [low, high] = divisor.to_le_bytes();
+            uart.write(LINE_CONTROL, LINE_DLAB);
+            uart.write(DATA, low);
+            uart.write(INTERRUPT_ENABLE, high);
+            uart.write(LINE_CONTROL, config.line_control());
+
+            uart.write(FIFO_CONTROL, FIFO_ENABLE_CLEAR_14);
+
+            uart.write(
+                MODEM_CONTROL,
+                MODEM_RTS | MODEM_OUT1 | MODEM_OUT2 | MODEM_LOOPBACK,
+            );
+            uart.write(DATA, LOOPBACK_TEST_BYTE);
+            let received = (0..LOOPBACK_TIMEOUT).find_map(|_| uart.try_receive());
+            if received != Some(LOOPBACK_TEST_BYTE) {
+                uart.write(MODEM_CONTROL, 0);
+                return Err(SerialError::LoopbackFailed);
+            }
+
+            uart.write(
+                MODEM_CONTROL,
+                MODEM_DTR | MODEM_RTS | MODEM_OUT1 | MODEM_OUT2,
+            );
+            uart.write(INTERRUPT_ENABLE, INTERRUPT_RECEIVED);
+        }
+
+        Ok(uart)
+    }
+
+    #[must_use]
+    pub fn base(&self) -> u16 {
+        self.base
+    }
+
+    #[must_use]
+    pub fn config(&self) -> Config {
+        self.config
+    }
+
+    /// Wait until the uart can take another byte, then send `byte`
+    pub fn send(&mut self, byte: u8) {
+        while self.line_status() & STATUS_TRANSMIT_EMPTY == 0 {
+            core::hint::spin_loop();
+        }
+        unsafe { self.write(DATA, byte) };
+    }
+
+    /// Read a received byte, if there is one
+    pub fn try_receive(&mut self) -> Option<u8> {
+        if self.line_status() & STATUS_DATA_READY == 0 {
+            return None;
+        }
+        Some(unsafe { Port::new(self.base + DATA).read() })
+    }
+
+    fn line_status(&self) -> u8 {
+        unsafe { Port::new(self.base + LINE_STATUS).read() }
+    }
+
+    unsafe fn write(&mut self, register: u16, value: u8) {
+        unsafe { Port::new(self.base + register).write(value) };
+    }
+}
+
+impl fmt::Write for Uart {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.send(byte);
+        }
+        Ok(())
+    }
+}
+
+#[test_case]
+fn test_line_control() {
+    assert_eq!(Config::DEFAULT.line_control(), 0x03);
+
+    let config = Config {
+        baud_rate: 9600,
+        data_bits: DataBits::Seven,
+        parity: Parity::Even,
+        stop_bits: StopBits::Two,
+    };
+    assert_eq!(config.line_control(), 0b0001_1110);
+    assert_eq!(config.divisor(), Ok(12));
+    assert_eq!(alloc::format!("{config}"), "9600 7E2");
+}
+
+#[test_case]
+fn test_invalid_baud_rate() {
+    for baud_rate in [0, 1, 7, 200_000] {
+        let config = Config {
+            baud_rate,
+            ..Config::DEFAULT
+        };
+        assert_eq!(
+            config.divisor(),
+            Err(SerialError::InvalidBaudRate(baud_rate))
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use super::Context;
+use crate::{
+    memory::{self, allocator},
+    serial::{self, ComPort},
+    task::{
+        self,
+        keyboard::{self, Layout},
+    },
+    thread, time,
+};
+
+/// A command the shell can run
+#[derive(Debug, Clone, Copy)]
+pub struct Command {
+    pub name: &'static str,
+    /// One line description, shown by `help`
+    pub help: &'static str,
+    /// Called with the arguments after the command's name
+    pub run: fn(&mut Context, &[&str]),
+}
+
+pub(super) const BUILTINS: &[Command] = &[
+    Command {
+        name: "help",
+        help: "list commands",
+        run: help,
+    },
+    Command {
+        name: "clear",
+        help: "clear the screen",
+        run: clear,
+    },
+    Command {
+        name: "uptime",
+        help: "show the time since boot",
+        run: uptime,
+    },
+    Command {
+        name: "mem",
+        help: "show heap and physical memory usage",
+        run: mem,
+    },
+    Command {
+        name: "tasks",
+        help: "list async tasks and threads",
+        run: tasks,
+    },
+    Command {
+        name: "layout",
+        help: "show or switch the keyboard layout",
+        run: layout,
+    },
+    Command {
+        name: "serial",
+        help: "list serial ports and their settings",
+        run: serial_ports,
+    },
+    Command {
+        name: "reboot",
+        help: "restart the machine",
+        run: reboot,
+    },
+];
+
+fn help(ctx: &mut Context, _args: &[&str]) {
+    let width = ctx.commands.iter().map(|c| c.name.len()).max().unwrap_or(0);
+    for command in ctx.commands {
+        let _ = writeln!(ctx.terminal, "{:width$}  {}", command.name, command.help);
+    }
+}
+
+fn clear(ctx: &mut Context, _args: &[&str]) {
+    ctx.terminal.clear_screen();
+}
+
+fn uptime(ctx: &mut Context, _args: &[&str]) {
+    let uptime = time::uptime();
+    let secs = uptime.as_secs();
+    let _ = writeln!(
+        ctx.terminal,
+        "up {}:{:02}:{:02}.{:03} ({} ticks)",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        uptime.subsec_millis(),
+        time::ticks(),
+    );
+}
+
+fn mem(ctx: &mut Context, _args: &[&str]) {
+    let heap = allocator::heap_stats();
+    let _ = writeln!(
+        ctx.terminal,
+        "heap: {} KiB allocated ({} KiB peak), {} KiB mapped",
+        heap.allocated_bytes / 1024,
+        heap.peak_allocated_bytes / 1024,
+        heap.heap_size / 1024,
+    );
+
+    let (used, total) =
+        memory::with_frame_allocator(|frames| (frames.used_frames(), frames.total_frames()));
+    let _ = writeln!(
+        ctx.terminal,
+        "frames: {used}/{total} used ({} KiB free)",
+        (total - used) * 4,
+    );
+}
+
+fn tasks(ctx: &mut Context, _args: &[&str]) {
+    let _ = writeln!(ctx.terminal, "async tasks: {}", task::task_count());
+
+    let current = thread::current();
+    for (id, state) in thread::threads() {
+        let marker = if Some(id) == current { '*' } else { ' ' };
+        let _ = writeln!(ctx.terminal, "{marker} {id:?}: {state:?}");
+    }
+}
+
+fn layout(ctx: &mut Context, args: &[&str]) {
+    let Some(name) = args.first() else {
+        let _ = write!(
+            ctx.terminal,
+            "layout: {}, available:",
+            keyboard::layout().name()
+        );
+        for layout in Layout::ALL {
+            let _ = write!(ctx.terminal, " {}", layout.name());
+        }
+        let _ = writeln!(ctx.terminal);
+        return;
+    };
+
+    match Layout::from_name(name) {
+        Some(layout) => keyboard::set_layout(layout),
+        None => {
+            let _ = writeln!(ctx.terminal, "unknown layout `{name}`");
+        }
+    }
+}
+
+fn serial_ports(ctx: &mut Context, _args: &[&str]) {
+    let log_port = serial::log_port();
+    for port in ComPort::ALL {
+        let _ = write!(ctx.terminal, "{}: ", port.name());
+        match serial::config(port) {
+            Some(config) => {
+                let _ = write!(ctx.terminal, "{config}");
+            }
+            None => {
+                let _ = write!(ctx.terminal, "not detected");
+            }
+        }
+        if Some(port) == log_port {
+            let _ = write!(ctx.terminal, " (log)");
+        }
+        let _ = writeln!(ctx.terminal);
+    }
+}
+
+fn reboot(_ctx: &mut Context, _args: &[&str]) {
+    crate::reboot();
+}
@@ -0,0 +1,135 @@
+use super::line::Key;
+
+const ESC: u8 = 0x1b;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    /// After an escape byte
+    Escape,
+    /// After `ESC [`, with the number read so far
+    Csi(u8),
+    /// After `ESC O`
+    Ss3,
+}
+
+/// Turns the bytes a VT100 compatible terminal sends into [`Key`]s
+#[derive(Debug, Clone, Copy)]
+pub struct ByteDecoder {
+    state: State,
+    /// Whether the last byte was a carriage return, so that a following line feed is not another enter
+    after_cr: bool,
+}
+
+impl ByteDecoder {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            state: State::Ground,
+            after_cr: false,
+        }
+    }
+
+    /// Feed one byte, returning a key once one was completed.
+    ///
+    /// Unknown escape sequences are dropped.
+    pub fn add_byte(&mut self, byte: u8) -> Option<Key> {
+        let after_cr = core::mem::replace(&mut self.after_cr, byte == b'\r');
+
+        match self.state {
+            State::Ground => self.ground(byte, after_cr),
+            State::Escape => {
+                self.state = match byte {
+                    b'[' => State::Csi(0),
+                    b'O' => State::Ss3,
+                    _ => State::Ground,
+                };
+                None
+            }
+            State::Csi(n) => match byte {
+                b'0'..=b'9' => {
+                    self.state = State::Csi(n.saturating_mul(10).saturating_add(byte - b'0'));
+                    None
+                }
+                // parameters after a `;` are modifiers, which are ignored
+                b';' => None,
+                b'~' => {
+                    self.state = State::Ground;
+                    match n {
+                        1 | 7 => Some(Key::Home),
+                        3 => Some(Key::Delete),
+                        4 | 8 => Some(Key::End),
+                        _ => None,
+                    }
+                }
+                _ => {
+                    self.state = State::Ground;
+                    Self::final_letter(byte)
+                }
+            },
+            State::Ss3 => {
+                self.state = State::Ground;
+                Self::final_letter(byte)
+            }
+        }
+    }
+
+    fn ground(&mut self, byte: u8, after_cr: bool) -> Option<Key> {
+        let key = match byte {
+            ESC => {
+                self.state = State::Escape;
+                return None;
+            }
+            // terminals send either of cr, lf or cr lf for enter
+            b'\n' if after_cr => return None,
+            b'\r' | b'\n' => Key::Enter,
+            0x08 | 0x7f => Key::Backspace,
+            0x01..=0x1a => Key::Ctrl(char::from(b'a' + byte - 1)),
+            0x20..=0x7e => Key::Char(char::from(byte)),
+            _ => return None,
+        };
+
+        Some(key)
+    }
+
+    /// The key for the letter ending `ESC [` and `ESC O` sequences
+    fn final_letter(byte: u8) -> Option<Key> {
+        let key = match byte {
+            b'A' => Key::Up,
+            b'B' => Key::Down,
+            b'C' => Key::Right,
+            b'D' => Key::Left,
+            b'H' => Key::Home,
+            b'F' => Key::End,
+            _ => return None,
+        };
+
+        Some(key)
+    }
+}
+
+impl Default for ByteDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test_case]
+fn test_decode_bytes() {
+    let mut decoder = ByteDecoder::new();
+    let mut decode = |bytes: &[u8]| -> alloc::vec::Vec<Key> {
+        bytes.iter().filter_map(|&b| decoder.add_byte(b)).collect()
+    };
+
+    assert_eq!(
+        decode(b"a\x7f\x03"),
+        [Key::Char('a'), Key::Backspace, Key::Ctrl('c')]
+    );
+    assert_eq!(decode(b"\r\n\n"), [Key::Enter, Key::Enter]);
+    assert_eq!(
+        decode(b"\x1b[A\x1bOD\x1b[3~\x1b[1;5C"),
+        [Key::Up, Key::Left, Key::Delete, Key::Right]
+    );
+    // unknown sequences are dropped without eating the next key
+    assert_eq!(decode(b"\x1b[99~x"), [Key::Char('x')]);
+}
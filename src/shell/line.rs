@@ -0,0 +1,356 @@
+use alloc::{collections::VecDeque, string::String};
+
+use super::terminal::Terminal;
+
+/// How many previous lines are remembered
+const HISTORY_SIZE: usize = 32;
+
+/// Keys the line editor understands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Backspace,
+    Delete,
+    Enter,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    /// A letter pressed while holding ctrl, in lowercase
+    Ctrl(char),
+}
+
+/// Edits a single line of input on a [`Terminal`], with history
+pub struct LineEditor {
+    line: String,
+    /// Byte index into `line`, which is always ascii
+    cursor: usize,
+    max_len: usize,
+    /// Oldest first
+    history: VecDeque<String>,
+    /// Which history entry is shown, if browsing the history
+    browsing: Option<usize>,
+    /// The line being typed before browsing the history
+    draft: String,
+}
+
+impl LineEditor {
+    /// Create an editor for lines of at most `max_len` characters
+    #[must_use]
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            line: String::new(),
+            cursor: 0,
+            max_len,
+            history: VecDeque::new(),
+            browsing: None,
+            draft: String::new(),
+        }
+    }
+
+    /// The line as typed so far
+    #[must_use]
+    pub fn line(&self) -> &str {
+        &self.line
+    }
+
+    /// Apply `key`, echoing the change to `terminal`.
+    ///
+    /// Returns the finished line when `key` is [`Key::Enter`].
+    pub fn handle(&mut self, key: Key, terminal: &mut dyn Terminal) -> Option<String> {
+        match key {
+            // only ascii, so that every character takes up exactly one column
+            Key::Char(c) if c.is_ascii_graphic() || c == ' ' => self.insert(c, terminal),
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                terminal.cursor_left(1);
+                self.line.remove(self.cursor);
+                self.redraw_rest(terminal);
+            }
+            Key::Delete if self.cursor < self.line.len() => {
+                self.line.remove(self.cursor);
+                self.redraw_rest(terminal);
+            }
+            Key::Left if self.cursor > 0 => {
+                self.cursor -= 1;
+                terminal.cursor_left(1);
+            }
+            Key::Right if self.cursor < self.line.len() => {
+                self.cursor += 1;
+                terminal.cursor_right(1);
+            }
+            Key::Home => {
+                terminal.cursor_left(self.cursor);
+                self.cursor = 0;
+            }
+            Key::End => {
+                terminal.cursor_right(self.line.len() - self.cursor);
+                self.cursor = self.line.len();
+            }
+            Key::Ctrl('a') => return self.handle(Key::Home, terminal),
+            Key::Ctrl('e') => return self.handle(Key::End, terminal),
+            // delete everything before the cursor
+            Key::Ctrl('u') => {
+                terminal.cursor_left(self.cursor);
+                self.line.drain(..self.cursor);
+                self.cursor = 0;
+                self.redraw_rest(terminal);
+            }
+            Key::Up => self.history_up(terminal),
+            Key::Down => self.history_down(terminal),
+            Key::Enter => return Some(self.finish(terminal)),
+            // keys that do nothing here, like backspace at the start of the line
+            _ => {}
+        }
+
+        None
+    }
+
+    /// Throw away the line without adding it to the history, and move to the next line
+    pub fn cancel(&mut self, terminal: &mut dyn Terminal) {
+        let _ = terminal.write_str("^C\n");
+        self.line.clear();
+        self.cursor = 0;
+        self.browsing = None;
+        self.draft.clear();
+    }
+
+    /// Draw the line again at the cursor, like after the screen was cleared
+    pub fn redraw(&self, terminal: &mut dyn Terminal) {
+        let _ = terminal.write_str(&self.line);
+        terminal.cursor_left(self.line.len() - self.cursor);
+    }
+
+    fn insert(&mut self, c: char, terminal: &mut dyn Terminal) {
+        if self.line.len() >= self.max_len {
+            return;
+        }
+
+        self.line.insert(self.cursor, c);
+        let _ = terminal.write_char(c);
+        self.cursor += 1;
+        self.redraw_rest(terminal);
+    }
+
+    /// Redraw the line after the cursor, leaving the cursor where it was
+    fn redraw_rest(&self, terminal: &mut dyn Terminal) {
+        let rest = &self.line[self.cursor..];
+        let _ = terminal.write_str(rest);
+        terminal.clear_line_end();
+        terminal.cursor_left(rest.len());
+    }
+
+    /// Replace the whole line with `line` and put the cursor at its end
+    fn replace(&mut self, line: String, terminal: &mut dyn Terminal) {
+        terminal.cursor_left(self.cursor);
+        let _ = terminal.write_str(&line);
+        terminal.clear_line_end();
+        self.cursor = line.len();
+        self.line = line;
+    }
+
+    fn history_up(&mut self, terminal: &mut dyn Terminal) {
+        let index = match self.browsing {
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = self.line.clone();
+                self.history.len() - 1
+            }
+            Some(0) => return,
+            Some(index) => index - 1,
+        };
+
+        self.browsing = Some(index);
+        self.replace(self.history[index].clone(), terminal);
+    }
+
+    fn history_down(&mut self, terminal: &mut dyn Terminal) {
+        let Some(index) = self.browsing else {
+            return;
+        };
+
+        if index + 1 < self.history.len() {
+            self.browsing = Some(index + 1);
+            self.replace(self.history[index + 1].clone(), terminal);
+        } else {
+            self.browsing = None;
+            let draft = core::mem::take(&mut self.draft);
+            self.replace(draft, terminal);
+        }
+    }
+
+    fn finish(&mut self, terminal: &mut dyn Terminal) -> String {
+        let _ = terminal.write_char('\n');
+        let line = core::mem::take(&mut self.line);
+        self.cursor = 0;
+        self.browsing = None;
+        self.draft.clear();
+
+        let is_repeat = self.history.back().is_some_and(|last| *last == line);
+        if !line.trim().is_empty() && !is_repeat {
+            if self.history.len() == HISTORY_SIZE {
+                self.history.pop_front();
+            }
+            self.history.push_back(line.clone());
+        }
+
+        line
+    }
+}
+
+/// A single line of a screen, to check what the editor echoes
+#[cfg(test)]
+struct TestTerminal {
+    line: [u8; 40],
+    cursor: usize,
+}
+
+#[cfg(test)]
+impl TestTerminal {
+    fn new() -> Self {
+        Self {
+            line: [b' '; 40],
+            cursor: 0,
+        }
+    }
+
+    fn shown(&self) -> &str {
+        core::str::from_utf8(&self.line)
+            .unwrap_or_default()
+            .trim_end()
+    }
+}
+
+#[cfg(test)]
+impl core::fmt::Write for TestTerminal {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                *self = Self::new();
+            } else {
+                self.line[self.cursor] = byte;
+                self.cursor += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Terminal for TestTerminal {
+    fn cursor_left(&mut self, n: usize) {
+        self.cursor -= n;
+    }
+
+    fn cursor_right(&mut self, n: usize) {
+        self.cursor += n;
+    }
+
+    fn clear_line_end(&mut self) {
+        self.line[self.cursor..].fill(b' ');
+    }
+
+    fn clear_screen(&mut self) {
+        *self = Self::new();
+    }
+
+    fn width(&self) -> usize {
+        self.line.len()
+    }
+}
+
+/// Press every key in `keys`, returning the line if the last one finished it
+#[cfg(test)]
+fn type_keys(editor: &mut LineEditor, terminal: &mut TestTerminal, keys: &[Key]) -> Option<String> {
+    let mut line = None;
+    for &key in keys {
+        line = editor.handle(key, terminal);
+    }
+    line
+}
+
+#[test_case]
+fn test_editing() {
+    let mut editor = LineEditor::new(30);
+    let mut terminal = TestTerminal::new();
+
+    let keys = [
+        Key::Char('h'),
+        Key::Char('l'),
+        Key::Char('o'),
+        Key::Left,
+        Key::Left,
+        Key::Char('e'),
+        Key::Right,
+        Key::Char('x'),
+        Key::Backspace,
+        Key::Char('l'),
+        Key::Home,
+        Key::Delete,
+        Key::Char('H'),
+        Key::End,
+        Key::Char('!'),
+    ];
+    assert!(type_keys(&mut editor, &mut terminal, &keys).is_none());
+    assert_eq!(editor.line(), "Hello!");
+    assert_eq!(terminal.shown(), "Hello!");
+    assert_eq!(terminal.cursor, 6);
+
+    let line = editor.handle(Key::Enter, &mut terminal);
+    assert_eq!(line.as_deref(), Some("Hello!"));
+    assert_eq!(editor.line(), "");
+}
+
+#[test_case]
+fn test_ctrl_keys() {
+    let mut editor = LineEditor::new(30);
+    let mut terminal = TestTerminal::new();
+
+    let keys = [
+        Key::Char('a'),
+        Key::Char('b'),
+        Key::Char('c'),
+        Key::Ctrl('a'),
+        Key::Right,
+        Key::Ctrl('u'),
+        Key::Ctrl('e'),
+        Key::Char('d'),
+    ];
+    type_keys(&mut editor, &mut terminal, &keys);
+    assert_eq!(editor.line(), "bcd");
+    assert_eq!(terminal.shown(), "bcd");
+    assert_eq!(terminal.cursor, 3);
+}
+
+#[test_case]
+fn test_history() {
+    let mut editor = LineEditor::new(30);
+    let mut terminal = TestTerminal::new();
+
+    for line in ["first", "second"] {
+        let mut keys: alloc::vec::Vec<Key> = line.chars().map(Key::Char).collect();
+        keys.push(Key::Enter);
+        type_keys(&mut editor, &mut terminal, &keys);
+    }
+
+    type_keys(
+        &mut editor,
+        &mut terminal,
+        &[Key::Char('x'), Key::Up, Key::Up],
+    );
+    assert_eq!(terminal.shown(), "first");
+
+    // past the oldest entry nothing changes
+    type_keys(&mut editor, &mut terminal, &[Key::Up]);
+    assert_eq!(editor.line(), "first");
+
+    type_keys(&mut editor, &mut terminal, &[Key::Down]);
+    assert_eq!(terminal.shown(), "second");
+
+    // back to what was being typed
+    type_keys(&mut editor, &mut terminal, &[Key::Down]);
+    assert_eq!(terminal.shown(), "x");
+    assert_eq!(editor.line(), "x");
+}
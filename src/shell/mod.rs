@@ -0,0 +1,276 @@
+mod commands;
+mod input;
+mod line;
+mod terminal;
+
+use alloc::vec::Vec;
+
+use futures_util::StreamExt;
+use log::info;
+use pc_keyboard::{DecodedKey, KeyCode};
+
+use crate::{
+    serial::{self, ComPort},
+    task::{
+        keyboard::{KeyEvent, KeyEventStream},
+        serial::SerialStream,
+    },
+    vga,
+};
+
+pub use commands::Command;
+pub use input::ByteDecoder;
+pub use line::{Key, LineEditor};
+pub use terminal::{SerialTerminal, Terminal, VgaTerminal};
+
+const PROMPT: &str = "> ";
+/// The virtual console [`run`] uses
+const SHELL_CONSOLE: usize = 1;
+
+/// What a running [`Command`] has access to
+pub struct Context<'a> {
+    pub terminal: &'a mut dyn Terminal,
+    /// Every registered command
+    pub commands: &'a [Command],
+}
+
+/// An interactive shell on a [`Terminal`]
+pub struct Shell<T: Terminal> {
+    terminal: T,
+    editor: LineEditor,
+    commands: Vec<Command>,
+}
+
+impl<T: Terminal> Shell<T> {
+    /// Create a shell with the built-in commands
+    pub fn new(terminal: T) -> Self {
+        // leave a column for the cursor after the last character
+        let max_len = terminal.width() - PROMPT.len() - 1;
+
+        Self {
+            terminal,
+            editor: LineEditor::new(max_len),
+            commands: commands::BUILTINS.to_vec(),
+        }
+    }
+
+    /// Add `command`, replacing any command with the same name
+    pub fn register(&mut self, command: Command) {
+        self.commands.retain(|c| c.name != command.name);
+        self.commands.push(command);
+    }
+
+    /// Print how to get help, then the prompt
+    pub fn greet(&mut self) {
+        let _ = writeln!(self.terminal, "\ntype `help` for a list of commands");
+        self.prompt();
+    }
+
+    pub fn prompt(&mut self) {
+        let _ = self.terminal.write_str(PROMPT);
+    }
+
+    /// Feed `key` to the line editor, running the line once it is finished
+    pub fn handle_key(&mut self, key: Key) {
+        match key {
+            Key::Ctrl('c') => {
+                self.editor.cancel(&mut self.terminal);
+                self.prompt();
+                return;
+            }
+            Key::Ctrl('l') => {
+                self.terminal.clear_screen();
+                self.prompt();
+                self.editor.redraw(&mut self.terminal);
+                return;
+            }
+            _ => {}
+        }
+
+        if let Some(line) = self.editor.handle(key, &mut self.terminal) {
+            self.execute(&line);
+            self.prompt();
+        }
+    }
+
+    /// Run the command on `line`, if there is one
+    pub fn execute(&mut self, line: &str) {
+        let mut words = line.split_whitespace();
+        let Some(name) = words.next() else {
+            return;
+        };
+        let args: Vec<&str> = words.collect();
+
+        let Some(command) = self.commands.iter().find(|c| c.name == name) else {
+            let _ = writeln!(self.terminal, "unknown command `{name}`, try `help`");
+            return;
+        };
+
+        let mut ctx = Context {
+            terminal: &mut self.terminal,
+            commands: &self.commands,
+        };
+        (command.run)(&mut ctx, &args);
+    }
+}
+
+/// Convert a key press into a key the line editor understands
+#[must_use]
+pub fn key_from_event(event: &KeyEvent) -> Option<Key> {
+    if let Some(c) = event.ctrl_char() {
+        return Some(Key::Ctrl(c));
+    }
+
+    let key = match event.key? {
+        DecodedKey::Unicode('\n') => Key::Enter,
+        DecodedKey::Unicode('\x08') => Key::Backspace,
+        DecodedKey::Unicode('\x7f') => Key::Delete,
+        DecodedKey::Unicode(c) => Key::Char(c),
+        DecodedKey::RawKey(KeyCode::ArrowLeft) => Key::Left,
+        DecodedKey::RawKey(KeyCode::ArrowRight) => Key::Right,
+        DecodedKey::RawKey(KeyCode::ArrowUp) => Key::Up,
+        DecodedKey::RawKey(KeyCode::ArrowDown) => Key::Down,
+        DecodedKey::RawKey(KeyCode::Home) => Key::Home,
+        DecodedKey::RawKey(KeyCode::End) => Key::End,
+        DecodedKey::RawKey(_) => return None,
+    };
+
+    Some(key)
+}
+
+/// Run a shell on the second virtual console and switch to it, reading keys from the keyboard while it is shown.
+///
+/// The first console is left to the kernel's output and logs, shown again with alt+f1.
+pub async fn run() {
+    let mut events = KeyEventStream::new();
+
+    let mut shell = Shell::new(VgaTerminal::new(&vga::CONSOLES[SHELL_CONSOLE]));
+    vga::switch_console(SHELL_CONSOLE);
+    shell.greet();
+
+    while let Some(event) = events.next().await {
+        if vga::active_console() != SHELL_CONSOLE {
+            continue;
+        }
+        if let Some(key) = key_from_event(&event) {
+            shell.handle_key(key);
+        }
+    }
+}
+
+/// Run a shell on `port`, for driving the kernel without a screen or keyboard.
+///
+/// If `port` is the log port, [`serial_print!`](crate::serial_print) output moves to the first other port that was
+/// detected, or is dropped if there is none, so that it does not end up in the middle of the shell.
+pub async fn run_serial(port: ComPort) {
+    if serial::log_port() == Some(port) {
+        let other = ComPort::ALL
+            .into_iter()
+            .find(|&other| other != port && serial::config(other).is_some());
+        if serial::set_log_port(other).is_ok() {
+            info!(
+                "serial log moved from {} to {}",
+                port.name(),
+                other.map_or("nowhere", ComPort::name)
+            );
+        }
+    }
+
+    let mut bytes = SerialStream::new(port);
+    let mut decoder = ByteDecoder::new();
+
+    let mut shell = Shell::new(SerialTerminal::new(port));
+    shell.greet();
+
+    while let Some(byte) = bytes.next().await {
+        if let Some(key) = decoder.add_byte(byte) {
+            shell.handle_key(key);
+        }
+    }
+}
+
+#[test_case]
+fn test_key_from_event() {
+    use crate::task::keyboard::Modifiers;
+    use pc_keyboard::KeyState;
+
+    let event = |code, key, modifiers| KeyEvent {
+        code,
+        state: KeyState::Down,
+        modifiers,
+        key,
+    };
+
+    let a = Some(DecodedKey::Unicode('a'));
+    assert_eq!(
+        key_from_event(&event(KeyCode::A, a, Modifiers::empty())),
+        Some(Key::Char('a'))
+    );
+    assert_eq!(
+        key_from_event(&event(KeyCode::A, a, Modifiers::CTRL)),
+        Some(Key::Ctrl('a'))
+    );
+
+    let up = Some(DecodedKey::RawKey(KeyCode::ArrowUp));
+    assert_eq!(
+        key_from_event(&event(KeyCode::ArrowUp, up, Modifiers::empty())),
+        Some(Key::Up)
+    );
+    // releases have no decoded key
+    assert_eq!(
+        key_from_event(&event(KeyCode::A, None, Modifiers::empty())),
+        None
+    );
+}
+
+/// A terminal that keeps everything written to it
+#[cfg(test)]
+struct CaptureTerminal(alloc::string::String);
+
+#[cfg(test)]
+impl core::fmt::Write for CaptureTerminal {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.0.push_str(s);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Terminal for CaptureTerminal {
+    fn width(&self) -> usize {
+        80
+    }
+}
+
+#[test_case]
+fn test_serial_shell() {
+    use core::task::{self, Poll};
+    use futures_util::task::noop_waker;
+
+    // nothing is connected to com4, so only these bytes are received on it
+    let port = ComPort::Com4;
+    let mut bytes = SerialStream::new(port);
+    for &byte in b"echo hx\x7fi\r\n" {
+        crate::task::serial::add_byte(port, byte);
+    }
+
+    let mut decoder = ByteDecoder::new();
+    let mut shell = Shell::new(CaptureTerminal(alloc::string::String::new()));
+    shell.register(Command {
+        name: "echo",
+        help: "print the arguments",
+        run: |ctx, args| {
+            let _ = writeln!(ctx.terminal, "{}", args.join(" "));
+        },
+    });
+
+    let waker = noop_waker();
+    let mut ctx = task::Context::from_waker(&waker);
+    while let Poll::Ready(Some(byte)) = bytes.poll_next_unpin(&mut ctx) {
+        if let Some(key) = decoder.add_byte(byte) {
+            shell.handle_key(key);
+        }
+    }
+
+    assert!(shell.terminal.0.ends_with("\nhi\n> "));
+}
@@ -0,0 +1,106 @@
+use core::fmt;
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::{
+    serial::{self, ComPort},
+    vga::{Writer, BUFFER_WIDTH},
+};
+
+/// Something the shell can print to and move the cursor around in.
+///
+/// The cursor always stays on the current line, so the line editor never has to deal with wrapping.
+/// By default the cursor is moved with VT100 escape sequences.
+pub trait Terminal: fmt::Write {
+    /// Move the cursor `n` columns left
+    fn cursor_left(&mut self, n: usize) {
+        if n > 0 {
+            let _ = write!(self, "\x1b[{n}D");
+        }
+    }
+
+    /// Move the cursor `n` columns right, over what is already on the line
+    fn cursor_right(&mut self, n: usize) {
+        if n > 0 {
+            let _ = write!(self, "\x1b[{n}C");
+        }
+    }
+
+    /// Erase everything from the cursor to the end of the line, without moving the cursor
+    fn clear_line_end(&mut self) {
+        let _ = self.write_str("\x1b[K");
+    }
+
+    /// Erase everything and move the cursor to the start of an empty line
+    fn clear_screen(&mut self) {
+        let _ = self.write_str("\x1b[2J\x1b[H");
+    }
+
+    /// Amount of columns in a line
+    fn width(&self) -> usize;
+}
+
+/// One of the virtual consoles in [`crate::vga::CONSOLES`], which understand the escape sequences
+#[derive(Clone, Copy)]
+pub struct VgaTerminal {
+    console: &'static Mutex<Writer>,
+}
+
+impl VgaTerminal {
+    #[must_use]
+    pub fn new(console: &'static Mutex<Writer>) -> Self {
+        Self { console }
+    }
+}
+
+impl fmt::Write for VgaTerminal {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        interrupts::without_interrupts(|| self.console.lock().write_str(s));
+        Ok(())
+    }
+}
+
+impl Terminal for VgaTerminal {
+    fn width(&self) -> usize {
+        BUFFER_WIDTH
+    }
+}
+
+/// A serial port, for a VT100 compatible terminal on the other end
+#[derive(Debug, Clone, Copy)]
+pub struct SerialTerminal {
+    port: ComPort,
+}
+
+impl SerialTerminal {
+    /// Columns assumed to be in a line, since the terminal's size is not known
+    const WIDTH: usize = 80;
+
+    #[must_use]
+    pub const fn new(port: ComPort) -> Self {
+        Self { port }
+    }
+}
+
+impl fmt::Write for SerialTerminal {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        serial::with_port(self.port, |uart| {
+            // the terminal is in raw mode, so it does not go back to the start of the line by itself
+            for (i, line) in s.split('\n').enumerate() {
+                if i > 0 {
+                    uart.write_str("\r\n")?;
+                }
+                uart.write_str(line)?;
+            }
+            Ok(())
+        })
+        .map_err(|_| fmt::Error)?
+    }
+}
+
+impl Terminal for SerialTerminal {
+    fn width(&self) -> usize {
+        Self::WIDTH
+    }
+}
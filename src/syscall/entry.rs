@@ -0,0 +1,100 @@
+use core::arch::{asm, naked_asm};
+
+use x86_64::{registers::rflags::RFlags, VirtAddr};
+
+use crate::gdt;
+
+/// User stack pointer while the syscall entry switches stacks.
+///
+/// The entry runs with interrupts disabled on a single cpu, so one scratch slot is enough.
+static mut USER_RSP: u64 = 0;
+
+/// Where `syscall` jumps to.
+///
+/// Switches to the kernel stack, saves the registers user code expects to survive, calls [`super::dispatch`]
+/// with the syscall number and arguments, then returns to user mode with the result in rax.
+#[unsafe(naked)]
+pub(super) unsafe extern "C" fn syscall_entry() {
+    naked_asm!(
+        "mov [rip + {user_rsp}], rsp",
+        "mov rsp, [rip + {kernel_stack}]",
+        "push qword ptr [rip + {user_rsp}]",
+        // rcx and r11 hold the user rip and rflags for `sysretq`
+        "push rcx",
+        "push r11",
+        "push rdi",
+        "push rsi",
+        "push rdx",
+        "push r8",
+        "push r9",
+        "push r10",
+        // keep the stack 16 byte aligned for the call
+        "sub rsp, 8",
+        // number in rax, arguments in rdi, rsi, rdx to the sysv abi
+        "mov rcx, rdx",
+        "mov rdx, rsi",
+        "mov rsi, rdi",
+        "mov rdi, rax",
+        // syscalls can be preempted like any other kernel code
+        "sti",
+        "call {dispatch}",
+        "cli",
+        "add rsp, 8",
+        "pop r10",
+        "pop r9",
+        "pop r8",
+        "pop rdx",
+        "pop rsi",
+        "pop rdi",
+        "pop r11",
+        "pop rcx",
+        "pop rsp",
+        "sysretq",
+        user_rsp = sym USER_RSP,
+        kernel_stack = sym gdt::KERNEL_STACK,
+        dispatch = sym super::dispatch,
+    );
+}
+
+/// Drop to ring 3 and start running at `entry` with the stack pointer set to `stack`.
+///
+/// # Safety
+///
+/// - `entry` and `stack` must be mapped with [`PageTableFlags::USER_ACCESSIBLE`](x86_64::structures::paging::PageTableFlags::USER_ACCESSIBLE).
+/// - The current thread must have been started with [`crate::thread::spawn_thread`], since the scheduler only
+///   sets the kernel stack used when entering the kernel again for threads it allocated a stack for.
+pub unsafe fn enter_user_mode(entry: VirtAddr, stack: VirtAddr) -> ! {
+    let selectors = gdt::selectors();
+    let rflags = RFlags::INTERRUPT_FLAG.bits() | 0b10; // bit 1 is reserved and always set
+
+    asm!(
+        "push {ss}",
+        "push {rsp}",
+        "push {rflags}",
+        "push {cs}",
+        "push {rip}",
+        // do not leak kernel values to user code
+        "xor eax, eax",
+        "xor ebx, ebx",
+        "xor ecx, ecx",
+        "xor edx, edx",
+        "xor esi, esi",
+        "xor edi, edi",
+        "xor ebp, ebp",
+        "xor r8d, r8d",
+        "xor r9d, r9d",
+        "xor r10d, r10d",
+        "xor r11d, r11d",
+        "xor r12d, r12d",
+        "xor r13d, r13d",
+        "xor r14d, r14d",
+        "xor r15d, r15d",
+        "iretq",
+        ss = in(reg) u64::from(selectors.user_data.0),
+        rsp = in(reg) stack.as_u64(),
+        rflags = in(reg) rflags,
+        cs = in(reg) u64::from(selectors.user_code.0),
+        rip = in(reg) entry.as_u64(),
+        options(noreturn),
+    );
+}
@@ -0,0 +1,169 @@
+mod entry;
+
+use core::str;
+
+use log::{info, trace};
+use x86_64::{
+    instructions::interrupts,
+    registers::{
+        model_specific::{Efer, EferFlags, LStar, SFMask, Star},
+        rflags::RFlags,
+    },
+    structures::paging::PageTableFlags,
+    VirtAddr,
+};
+
+use crate::{
+    gdt,
+    memory::{self, address_space},
+    print, thread,
+};
+
+pub use entry::enter_user_mode;
+
+/// Syscall numbers, passed in rax
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)]
+pub enum Syscall {
+    /// `write(fd, buf, len)`, returns the amount of bytes written
+    Write = 0,
+    /// `exit(code)`, never returns
+    Exit = 1,
+    /// `yield()`, lets other threads run
+    Yield = 2,
+}
+
+impl TryFrom<u64> for Syscall {
+    type Error = SyscallError;
+
+    fn try_from(number: u64) -> Result<Self, Self::Error> {
+        match number {
+            0 => Ok(Self::Write),
+            1 => Ok(Self::Exit),
+            2 => Ok(Self::Yield),
+            _ => Err(SyscallError::NoSuchSyscall),
+        }
+    }
+}
+
+/// Errors returned to user code as negative numbers in rax, with the same values as linux
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)]
+pub enum SyscallError {
+    BadFileDescriptor = 9,
+    /// A pointer argument points to memory user code cannot access
+    BadAddress = 14,
+    NoSuchSyscall = 38,
+}
+
+/// Enable `syscall`/`sysret` and point `syscall` at the kernel's entry
+///
+/// # Panics
+///
+/// Will panic if the GDT does not have its segments in the order `sysret` expects.
+pub fn init() {
+    trace!("enabling syscalls");
+    let selectors = gdt::selectors();
+
+    Star::write(
+        selectors.user_code,
+        selectors.user_data,
+        selectors.code,
+        selectors.data,
+    )
+    .expect("gdt segments are in the wrong order for sysret");
+    LStar::write(VirtAddr::new(entry::syscall_entry as *const () as u64));
+    // the entry must not be interrupted before it switches to the kernel stack
+    SFMask::write(RFlags::INTERRUPT_FLAG | RFlags::DIRECTION_FLAG | RFlags::TRAP_FLAG);
+
+    unsafe { Efer::update(|flags| flags.insert(EferFlags::SYSTEM_CALL_EXTENSIONS)) };
+}
+
+/// Called by the syscall entry with the syscall number and arguments
+extern "C" fn dispatch(number: u64, arg0: u64, arg1: u64, arg2: u64) -> u64 {
+    let result = match Syscall::try_from(number) {
+        Ok(Syscall::Write) => write(arg0, VirtAddr::try_new(arg1).ok(), arg2),
+        Ok(Syscall::Exit) => exit(arg0),
+        Ok(Syscall::Yield) => {
+            thread::yield_now();
+            Ok(0)
+        }
+        Err(err) => Err(err),
+    };
+
+    match result {
+        Ok(value) => value,
+        Err(err) => (err as u64).wrapping_neg(),
+    }
+}
+
+fn write(fd: u64, buf: Option<VirtAddr>, len: u64) -> Result<u64, SyscallError> {
+    if fd != 1 && fd != 2 {
+        return Err(SyscallError::BadFileDescriptor);
+    }
+    let buf = buf.ok_or(SyscallError::BadAddress)?;
+    if buf.as_u64() < address_space::USER_START
+        || buf
+            .as_u64()
+            .checked_add(len)
+            .is_none_or(|end| end > address_space::USER_END)
+    {
+        return Err(SyscallError::BadAddress);
+    }
+
+    // copied in chunks, so that nothing is printed while the page tables are locked
+    let mut chunk = [0u8; 256];
+    let mut written = 0;
+    while written < len {
+        let start = buf + written;
+        let chunk_len = (len - written).min(chunk.len() as u64);
+        let chunk = &mut chunk[..usize::try_from(chunk_len).unwrap_or(0)];
+        copy_from_user(start, chunk)?;
+
+        match str::from_utf8(chunk) {
+            Ok(text) => print!("{text}"),
+            Err(_) => chunk.iter().for_each(|&b| print!("{}", char::from(b))),
+        }
+
+        written += chunk_len;
+    }
+
+    Ok(len)
+}
+
+/// Copy `dest.len()` bytes at `src` in user memory into `dest`
+///
+/// # Errors
+///
+/// Will error if any of the bytes are not mapped as user accessible in the active address space.
+fn copy_from_user(src: VirtAddr, dest: &mut [u8]) -> Result<(), SyscallError> {
+    // nothing can unmap the memory while interrupts are disabled
+    interrupts::without_interrupts(|| {
+        let mut copied = 0;
+        while copied < dest.len() {
+            let addr = src + copied as u64;
+            let (phys, flags) =
+                address_space::translate_active(addr).ok_or(SyscallError::BadAddress)?;
+            let user_range = address_space::USER_START..address_space::USER_END;
+            if !user_range.contains(&addr.as_u64())
+                || !flags.contains(PageTableFlags::USER_ACCESSIBLE)
+            {
+                return Err(SyscallError::BadAddress);
+            }
+
+            // up to the end of the page, since the next page can be anywhere in physical memory
+            let page_left = 4096 - usize::from(addr.page_offset());
+            let len = page_left.min(dest.len() - copied);
+
+            let src: *const u8 = memory::phys_to_virt(phys).as_ptr();
+            unsafe { src.copy_to_nonoverlapping(dest[copied..].as_mut_ptr(), len) };
+            copied += len;
+        }
+        Ok(())
+    })
+}
+
+fn exit(code: u64) -> ! {
+    info!("thread {:?} exited with code {code}", thread::current());
+    thread::exit();
+}
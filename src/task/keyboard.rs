@@ -1,31 +1,34 @@
 use core::{
+    ops::{BitOr, BitOrAssign},
     pin::Pin,
+    sync::atomic::{AtomicU8, Ordering},
     task::{Context, Poll},
 };
 
-use conquer_once::spin::OnceCell;
-use crossbeam_queue::ArrayQueue;
-use futures_util::{task::AtomicWaker, Stream, StreamExt};
-use log::{error, warn};
-use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1};
+use futures_util::{Stream, StreamExt};
+use log::warn;
+use pc_keyboard::{
+    layouts::{self, AnyLayout},
+    DecodedKey, EventDecoder, HandleControl, KeyCode, KeyState, ScancodeSet, ScancodeSet1,
+};
 
-use crate::{print, vga::WRITER};
+use super::queue::{IrqQueue, QueueStream};
+use crate::{
+    ps2::{self, keyboard::Leds},
+    vga,
+};
 
-static WAKER: AtomicWaker = AtomicWaker::new();
+static SCANCODES: IrqQueue<u8> = IrqQueue::new("scancode");
 
 pub struct ScancodeStream {
-    _private: (),
+    scancodes: QueueStream<u8>,
 }
 
 impl ScancodeStream {
     pub fn new() -> Self {
-        if SCANCODE_QUEUE
-            .try_init_once(|| ArrayQueue::new(100))
-            .is_err()
-        {
-            error!("scancode queue initialised twice");
+        Self {
+            scancodes: SCANCODES.stream(),
         }
-        ScancodeStream { _private: () }
     }
 }
 
@@ -35,65 +38,433 @@ impl Default for ScancodeStream {
     }
 }
 
-static SCANCODE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
-
 pub fn add_scancode(scancode: u8) {
-    if let Ok(queue) = SCANCODE_QUEUE.try_get() {
-        if queue.push(scancode).is_err() {
-            warn!("failed to push to scancode queue, full");
+    SCANCODES.push(scancode);
+}
+
+impl Stream for ScancodeStream {
+    type Item = u8;
+
+    fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.scancodes.poll_next_unpin(ctx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.scancodes.size_hint()
+    }
+}
+
+/// Keyboard layouts that can be switched between with [`set_layout`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Layout {
+    Us104,
+    Uk105,
+    De105,
+    Azerty,
+    Dvorak104,
+    DvorakProgrammer104,
+    Colemak,
+    Jis109,
+    No105,
+    FiSe105,
+}
+
+impl Layout {
+    pub const ALL: [Layout; 10] = [
+        Layout::Us104,
+        Layout::Uk105,
+        Layout::De105,
+        Layout::Azerty,
+        Layout::Dvorak104,
+        Layout::DvorakProgrammer104,
+        Layout::Colemak,
+        Layout::Jis109,
+        Layout::No105,
+        Layout::FiSe105,
+    ];
+
+    /// A short name for the layout, like `us` or `de`
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Layout::Us104 => "us",
+            Layout::Uk105 => "uk",
+            Layout::De105 => "de",
+            Layout::Azerty => "fr",
+            Layout::Dvorak104 => "dvorak",
+            Layout::DvorakProgrammer104 => "dvp",
+            Layout::Colemak => "colemak",
+            Layout::Jis109 => "jp",
+            Layout::No105 => "no",
+            Layout::FiSe105 => "fi-se",
+        }
+    }
+
+    /// Find a layout by its [`Layout::name`]
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|layout| layout.name() == name)
+    }
+
+    fn to_any(self) -> AnyLayout {
+        match self {
+            Layout::Us104 => AnyLayout::Us104Key(layouts::Us104Key),
+            Layout::Uk105 => AnyLayout::Uk105Key(layouts::Uk105Key),
+            Layout::De105 => AnyLayout::De105Key(layouts::De105Key),
+            Layout::Azerty => AnyLayout::Azerty(layouts::Azerty),
+            Layout::Dvorak104 => AnyLayout::Dvorak104Key(layouts::Dvorak104Key),
+            Layout::DvorakProgrammer104 => AnyLayout::DVP104Key(layouts::DVP104Key),
+            Layout::Colemak => AnyLayout::Colemak(layouts::Colemak),
+            Layout::Jis109 => AnyLayout::Jis109Key(layouts::Jis109Key),
+            Layout::No105 => AnyLayout::No105Key(layouts::No105Key),
+            Layout::FiSe105 => AnyLayout::FiSe105Key(layouts::FiSe105Key),
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        Self::ALL
+            .get(usize::from(value))
+            .copied()
+            .unwrap_or(Layout::Us104)
+    }
+}
+
+/// The layout [`process_keys`] decodes keys with
+static LAYOUT: AtomicU8 = AtomicU8::new(Layout::Us104 as u8);
+
+/// Switch the layout keys are decoded with. Takes effect from the next key event.
+pub fn set_layout(layout: Layout) {
+    LAYOUT.store(layout as u8, Ordering::Relaxed);
+}
+
+#[must_use]
+pub fn layout() -> Layout {
+    Layout::from_u8(LAYOUT.load(Ordering::Relaxed))
+}
+
+/// A set of modifier keys that are held down or toggled on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers(u8);
+
+impl Modifiers {
+    pub const SHIFT: Self = Self(1 << 0);
+    pub const CTRL: Self = Self(1 << 1);
+    pub const ALT: Self = Self(1 << 2);
+    pub const ALT_GR: Self = Self(1 << 3);
+    pub const CAPS_LOCK: Self = Self(1 << 4);
+    pub const NUM_LOCK: Self = Self(1 << 5);
+    pub const SCROLL_LOCK: Self = Self(1 << 6);
+
+    #[must_use]
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Whether every modifier in `other` is in this set
+    #[must_use]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn set(&mut self, other: Self, value: bool) {
+        if value {
+            self.0 |= other.0;
         } else {
-            WAKER.wake();
+            self.0 &= !other.0;
         }
-    } else {
-        warn!("scancode queue not init");
     }
 }
 
-pub async fn print_keypresses() {
-    let mut scancodes = ScancodeStream::new();
-    let mut keyboard = Keyboard::new(
-        ScancodeSet1::new(),
-        layouts::Us104Key,
-        HandleControl::Ignore,
-    );
+impl BitOr for Modifiers {
+    type Output = Self;
 
-    while let Some(scancode) = scancodes.next().await {
-        if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
-            if let Some(key) = keyboard.process_keyevent(key_event) {
-                match key {
-                    // backspace
-                    DecodedKey::Unicode(character) if character == 0x08 as char => {
-                        WRITER.lock().backspace();
-                    }
-                    DecodedKey::Unicode(character) => print!("{character}"),
-                    DecodedKey::RawKey(_) => (),
-                }
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Modifiers {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// A key being pressed or released
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub code: KeyCode,
+    pub state: KeyState,
+    /// The modifiers after this event was applied
+    pub modifiers: Modifiers,
+    /// What the key means in the current layout, only for presses
+    pub key: Option<DecodedKey>,
+}
+
+impl KeyEvent {
+    #[must_use]
+    pub fn is_pressed(&self) -> bool {
+        self.state != KeyState::Up
+    }
+
+    /// The letter pressed while holding ctrl, in lowercase, like `c` for ctrl+c
+    #[must_use]
+    pub fn ctrl_char(&self) -> Option<char> {
+        if !self.modifiers.contains(Modifiers::CTRL) || self.modifiers.contains(Modifiers::ALT_GR) {
+            return None;
+        }
+
+        match self.key {
+            Some(DecodedKey::Unicode(c)) if c.is_ascii_alphabetic() => Some(c.to_ascii_lowercase()),
+            _ => None,
+        }
+    }
+}
+
+/// Turns scancodes into [`KeyEvent`]s, keeping track of modifiers
+pub struct KeyDecoder {
+    scancode_set: ScancodeSet1,
+    decoder: EventDecoder<AnyLayout>,
+    layout: Layout,
+    /// Which modifier keys are held, by side
+    held: pc_keyboard::Modifiers,
+    scroll_lock: bool,
+}
+
+impl KeyDecoder {
+    #[must_use]
+    pub fn new(layout: Layout) -> Self {
+        Self {
+            scancode_set: ScancodeSet1::new(),
+            // ctrl combinations are reported through `KeyEvent::modifiers` instead
+            decoder: EventDecoder::new(layout.to_any(), HandleControl::Ignore),
+            layout,
+            held: pc_keyboard::Modifiers {
+                // the keyboard starts with num lock on
+                numlock: true,
+                ..Default::default()
+            },
+            scroll_lock: false,
+        }
+    }
+
+    #[must_use]
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+        self.decoder.change_layout(layout.to_any());
+    }
+
+    #[must_use]
+    pub fn modifiers(&self) -> Modifiers {
+        let held = &self.held;
+        let mut modifiers = Modifiers::empty();
+        modifiers.set(Modifiers::SHIFT, held.is_shifted());
+        modifiers.set(Modifiers::CTRL, held.is_ctrl());
+        modifiers.set(Modifiers::ALT, held.lalt);
+        modifiers.set(Modifiers::ALT_GR, held.ralt);
+        modifiers.set(Modifiers::CAPS_LOCK, held.capslock);
+        modifiers.set(Modifiers::NUM_LOCK, held.numlock);
+        modifiers.set(Modifiers::SCROLL_LOCK, self.scroll_lock);
+        modifiers
+    }
+
+    /// Feed one scancode byte, returning an event once a whole key event was received
+    pub fn add_byte(&mut self, scancode: u8) -> Option<KeyEvent> {
+        let event = match self.scancode_set.advance_state(scancode) {
+            Ok(Some(event)) => event,
+            Ok(None) => return None,
+            Err(err) => {
+                warn!("bad scancode {scancode:#x}: {err:?}");
+                return None;
             }
+        };
+
+        self.track(&event);
+        let key = self.decoder.process_keyevent(event.clone());
+
+        Some(KeyEvent {
+            code: event.code,
+            state: event.state,
+            modifiers: self.modifiers(),
+            key,
+        })
+    }
+
+    /// Update which modifiers are held the same way the [`EventDecoder`] does
+    fn track(&mut self, event: &pc_keyboard::KeyEvent) {
+        let down = event.state == KeyState::Down;
+        let held = &mut self.held;
+
+        match event.code {
+            KeyCode::LShift => held.lshift = down,
+            KeyCode::RShift => held.rshift = down,
+            KeyCode::LControl => held.lctrl = down,
+            KeyCode::RControl => held.rctrl = down,
+            KeyCode::RControl2 => held.rctrl2 = down,
+            KeyCode::LAlt => held.lalt = down,
+            KeyCode::RAltGr => held.ralt = down,
+            KeyCode::CapsLock if down => held.capslock = !held.capslock,
+            // pause sends num lock with the hidden right control
+            KeyCode::NumpadLock if down && !held.rctrl2 => held.numlock = !held.numlock,
+            KeyCode::ScrollLock if down => self.scroll_lock = !self.scroll_lock,
+            _ => {}
         }
     }
 }
 
-impl Stream for ScancodeStream {
-    type Item = u8;
+static EVENTS: IrqQueue<KeyEvent> = IrqQueue::new("key event");
 
-    fn poll_next(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let queue = SCANCODE_QUEUE.try_get().expect("scancode queue not init");
+/// Decode the keyboard's scancodes with the layout set by [`set_layout`], and react to the keys the system handles
+/// itself before passing every event on to the [`KeyEventStream`]:
+/// - the keyboard LEDs follow caps lock, num lock and scroll lock
+/// - page up and page down scroll the active console
+/// - alt+f1 to alt+f6 switch consoles
+///
+/// Spawn this once, since there can only be one [`ScancodeStream`].
+pub async fn process_keys() {
+    let mut scancodes = ScancodeStream::new();
+    let mut decoder = KeyDecoder::new(layout());
 
-        if let Some(scancode) = queue.pop() {
-            return Poll::Ready(Some(scancode));
+    while let Some(scancode) = scancodes.next().await {
+        let layout = layout();
+        if decoder.layout() != layout {
+            decoder.set_layout(layout);
         }
 
-        WAKER.register(ctx.waker());
-        if let Some(scancode) = queue.pop() {
-            WAKER.take();
-            Poll::Ready(Some(scancode))
-        } else {
-            Poll::Pending
+        let before = decoder.modifiers();
+        let Some(event) = decoder.add_byte(scancode) else {
+            continue;
+        };
+
+        if leds(before) != leds(event.modifiers) {
+            ps2::keyboard::set_leds(leds(event.modifiers));
+        }
+        if event.is_pressed() {
+            console_keys(&event);
         }
+        EVENTS.push(event);
+    }
+}
+
+/// A stream of the [`KeyEvent`]s passed on by [`process_keys`]
+pub struct KeyEventStream {
+    events: QueueStream<KeyEvent>,
+}
+
+impl KeyEventStream {
+    /// Start listening for key events. Events before this are dropped, and
+    /// nothing is ever yielded unless [`process_keys`] is spawned.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            events: EVENTS.stream(),
+        }
+    }
+}
+
+impl Default for KeyEventStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stream for KeyEventStream {
+    type Item = KeyEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.events.poll_next_unpin(ctx)
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        // capacity is 100
-        (0, Some(100))
+        self.events.size_hint()
     }
 }
+
+/// Scroll the active console through its history with page up and page down,
+/// and switch to another console with alt+f1 to alt+f6
+fn console_keys(event: &KeyEvent) {
+    let page = vga::BUFFER_HEIGHT - 1;
+    let alt = event.modifiers.contains(Modifiers::ALT);
+    match event.code {
+        KeyCode::PageUp => vga::scroll_up(page),
+        KeyCode::PageDown => vga::scroll_down(page),
+        KeyCode::F1 if alt => vga::switch_console(0),
+        KeyCode::F2 if alt => vga::switch_console(1),
+        KeyCode::F3 if alt => vga::switch_console(2),
+        KeyCode::F4 if alt => vga::switch_console(3),
+        KeyCode::F5 if alt => vga::switch_console(4),
+        KeyCode::F6 if alt => vga::switch_console(5),
+        _ => {}
+    }
+}
+
+/// Which LEDs should be on for `modifiers`
+fn leds(modifiers: Modifiers) -> Leds {
+    Leds {
+        scroll_lock: modifiers.contains(Modifiers::SCROLL_LOCK),
+        num_lock: modifiers.contains(Modifiers::NUM_LOCK),
+        caps_lock: modifiers.contains(Modifiers::CAPS_LOCK),
+    }
+}
+
+#[cfg(test)]
+fn press(decoder: &mut KeyDecoder, scancodes: &[u8]) -> alloc::vec::Vec<KeyEvent> {
+    scancodes
+        .iter()
+        .filter_map(|&scancode| decoder.add_byte(scancode))
+        .collect()
+}
+
+#[test_case]
+fn test_modifiers() {
+    let mut decoder = KeyDecoder::new(Layout::Us104);
+
+    // left shift down, a down, a up, left shift up
+    let events = press(&mut decoder, &[0x2a, 0x1e, 0x9e, 0xaa]);
+    assert_eq!(events.len(), 4);
+    assert!(events[1].modifiers.contains(Modifiers::SHIFT));
+    assert_eq!(events[1].key, Some(DecodedKey::Unicode('A')));
+    assert!(!events[2].is_pressed());
+    assert!(!events[3].modifiers.contains(Modifiers::SHIFT));
+
+    // caps lock toggles
+    press(&mut decoder, &[0x3a, 0xba]);
+    assert!(decoder.modifiers().contains(Modifiers::CAPS_LOCK));
+}
+
+#[test_case]
+fn test_ctrl_char() {
+    let mut decoder = KeyDecoder::new(Layout::Us104);
+
+    // left ctrl down, c down
+    let events = press(&mut decoder, &[0x1d, 0x2e]);
+    assert_eq!(events[1].ctrl_char(), Some('c'));
+
+    // left ctrl up, c down
+    let events = press(&mut decoder, &[0x9d, 0x2e]);
+    assert_eq!(events[1].ctrl_char(), None);
+    assert_eq!(events[1].key, Some(DecodedKey::Unicode('c')));
+}
+
+#[test_case]
+fn test_switch_layout() {
+    let mut decoder = KeyDecoder::new(Layout::Us104);
+    // the key right of t is y on us keyboards and z on german ones
+    assert_eq!(
+        press(&mut decoder, &[0x15])[0].key,
+        Some(DecodedKey::Unicode('y'))
+    );
+
+    decoder.set_layout(Layout::De105);
+    assert_eq!(
+        press(&mut decoder, &[0x15])[0].key,
+        Some(DecodedKey::Unicode('z'))
+    );
+
+    assert_eq!(Layout::from_name("de"), Some(Layout::De105));
+    assert_eq!(Layout::from_name("klingon"), None);
+}
@@ -1,11 +1,15 @@
 pub mod executor;
 pub mod keyboard;
+pub mod mouse;
+pub mod queue;
+pub mod serial;
+pub mod timer;
 
 use alloc::boxed::Box;
 use core::{
     future::Future,
     pin::Pin,
-    sync::atomic::{AtomicU64, Ordering},
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
     task::{Context, Poll},
 };
 
@@ -19,6 +23,15 @@ impl TaskId {
     }
 }
 
+/// Amount of tasks created and not dropped yet
+static LIVE_TASKS: AtomicUsize = AtomicUsize::new(0);
+
+/// Get the amount of tasks that have not completed yet, across all executors
+#[must_use]
+pub fn task_count() -> usize {
+    LIVE_TASKS.load(Ordering::Relaxed)
+}
+
 pub struct Task {
     id: TaskId,
     future: Pin<Box<dyn Future<Output = ()>>>,
@@ -26,6 +39,7 @@ pub struct Task {
 
 impl Task {
     pub fn new(future: impl Future<Output = ()> + 'static) -> Self {
+        LIVE_TASKS.fetch_add(1, Ordering::Relaxed);
         Self {
             id: TaskId::new(),
             future: Box::pin(future),
@@ -36,3 +50,9 @@ impl Task {
         self.future.as_mut().poll(context)
     }
 }
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        LIVE_TASKS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,48 @@
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_util::{Stream, StreamExt};
+
+use super::queue::{IrqQueue, QueueStream};
+use crate::ps2::mouse::MouseEvent;
+
+static EVENTS: IrqQueue<MouseEvent> = IrqQueue::new("mouse event");
+
+/// A stream of [`MouseEvent`]s from the mouse interrupt
+pub struct MouseStream {
+    events: QueueStream<MouseEvent>,
+}
+
+impl MouseStream {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            events: EVENTS.stream(),
+        }
+    }
+}
+
+impl Default for MouseStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Called by the mouse interrupt handler with every decoded packet
+pub(crate) fn add_event(event: MouseEvent) {
+    EVENTS.push(event);
+}
+
+impl Stream for MouseStream {
+    type Item = MouseEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.events.poll_next_unpin(ctx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.events.size_hint()
+    }
+}
@@ -0,0 +1,92 @@
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use conquer_once::spin::OnceCell;
+use crossbeam_queue::ArrayQueue;
+use futures_util::{task::AtomicWaker, Stream};
+use log::{error, warn};
+
+/// How many items an [`IrqQueue`] holds before it drops new ones
+const CAPACITY: usize = 100;
+
+/// A queue that interrupt handlers push to without blocking, drained by a single [`QueueStream`].
+///
+/// Nothing is allocated until [`IrqQueue::stream`] is called, so that the handlers can run before the heap exists.
+pub struct IrqQueue<T> {
+    /// What is queued, for log messages
+    name: &'static str,
+    queue: OnceCell<ArrayQueue<T>>,
+    waker: AtomicWaker,
+}
+
+impl<T> IrqQueue<T> {
+    #[must_use]
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            queue: OnceCell::uninit(),
+            waker: AtomicWaker::new(),
+        }
+    }
+
+    /// Start listening. Everything pushed before this was dropped.
+    ///
+    /// There should only be one stream per queue, since they would take turns getting items.
+    pub fn stream(&'static self) -> QueueStream<T> {
+        if self
+            .queue
+            .try_init_once(|| ArrayQueue::new(CAPACITY))
+            .is_err()
+        {
+            error!("{} queue initialised twice", self.name);
+        }
+        QueueStream { queue: self }
+    }
+
+    /// Queue `item` and wake the stream. Dropped if nobody is listening yet, or the queue is full.
+    pub fn push(&self, item: T) {
+        // nobody is listening
+        let Ok(queue) = self.queue.try_get() else {
+            return;
+        };
+
+        if queue.push(item).is_err() {
+            warn!("failed to push to {} queue, full", self.name);
+        } else {
+            self.waker.wake();
+        }
+    }
+}
+
+/// A stream of the items pushed to an [`IrqQueue`]
+pub struct QueueStream<T: 'static> {
+    queue: &'static IrqQueue<T>,
+}
+
+impl<T> Stream for QueueStream<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let waker = &self.queue.waker;
+        // initialised when the stream was created
+        let queue = self.queue.queue.try_get().expect("queue not init");
+
+        if let Some(item) = queue.pop() {
+            return Poll::Ready(Some(item));
+        }
+
+        waker.register(ctx.waker());
+        if let Some(item) = queue.pop() {
+            waker.take();
+            Poll::Ready(Some(item))
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(CAPACITY))
+    }
+}
@@ -0,0 +1,48 @@
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_util::{Stream, StreamExt};
+
+use super::queue::{IrqQueue, QueueStream};
+use crate::serial::ComPort;
+
+/// One per port, indexed by [`ComPort::index`]
+static BYTES: [IrqQueue<u8>; 4] = [
+    IrqQueue::new("COM1 byte"),
+    IrqQueue::new("COM2 byte"),
+    IrqQueue::new("COM3 byte"),
+    IrqQueue::new("COM4 byte"),
+];
+
+/// A stream of the bytes received on a serial port, from its interrupt
+pub struct SerialStream {
+    bytes: QueueStream<u8>,
+}
+
+impl SerialStream {
+    #[must_use]
+    pub fn new(port: ComPort) -> Self {
+        Self {
+            bytes: BYTES[port.index()].stream(),
+        }
+    }
+}
+
+/// Called by the serial interrupt handlers with every byte they read
+pub(crate) fn add_byte(port: ComPort, byte: u8) {
+    BYTES[port.index()].push(byte);
+}
+
+impl Stream for SerialStream {
+    type Item = u8;
+
+    fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.bytes.poll_next_unpin(ctx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.bytes.size_hint()
+    }
+}
@@ -0,0 +1,255 @@
+use core::{
+    cmp::{Ordering, Reverse},
+    future::Future,
+    pin::Pin,
+    sync::atomic::{self, AtomicU64},
+    task::{Context, Poll, Waker},
+};
+
+use alloc::collections::BinaryHeap;
+use futures_util::Stream;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::time::{Duration, Instant};
+
+/// A registered wake-up, ordered by its deadline
+struct Timer {
+    deadline: Instant,
+    id: u64,
+    waker: Waker,
+}
+
+impl PartialEq for Timer {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Timer {}
+
+impl PartialOrd for Timer {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Timer {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.deadline, self.id).cmp(&(other.deadline, other.id))
+    }
+}
+
+/// Pending timers, earliest deadline first.
+///
+/// Only ever locked with interrupts disabled, since the timer interrupt locks it too.
+static TIMERS: Mutex<BinaryHeap<Reverse<Timer>>> = Mutex::new(BinaryHeap::new());
+
+fn next_timer_id() -> u64 {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    NEXT_ID.fetch_add(1, atomic::Ordering::Relaxed)
+}
+
+/// Wake every timer whose deadline has passed. Called by the timer interrupt handler.
+///
+/// Popping from the heap never shrinks it, but waking consumes the [`Waker`]. If that was the last reference to a task
+/// that already finished, the task is freed here, in the interrupt handler.
+pub(crate) fn wake_expired() {
+    let now = Instant::now();
+
+    // if task code somehow holds the lock, try again next tick instead of deadlocking
+    let Some(mut timers) = TIMERS.try_lock() else {
+        return;
+    };
+
+    while timers
+        .peek()
+        .is_some_and(|Reverse(timer)| timer.deadline <= now)
+    {
+        if let Some(Reverse(timer)) = timers.pop() {
+            timer.waker.wake();
+        }
+    }
+}
+
+/// A future that completes once its deadline has passed
+#[must_use = "futures do nothing unless polled"]
+pub struct Sleep {
+    deadline: Instant,
+    /// The id of the timer registered for this future, if any
+    timer: Option<u64>,
+}
+
+/// Wait until `duration` has passed
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(Instant::now() + duration)
+}
+
+/// Wait until `deadline` has passed
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep {
+        deadline,
+        timer: None,
+    }
+}
+
+impl Sleep {
+    #[must_use]
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// Change the deadline of this future, which makes it pending again if the new deadline is in the future
+    pub fn reset(&mut self, deadline: Instant) {
+        self.deadline = deadline;
+        self.deregister();
+    }
+
+    /// Register `waker` to be woken at the deadline, replacing any previously registered waker
+    fn register(&mut self, waker: &Waker) {
+        let id = next_timer_id();
+        let timer = Timer {
+            deadline: self.deadline,
+            id,
+            waker: waker.clone(),
+        };
+
+        interrupts::without_interrupts(|| {
+            let mut timers = TIMERS.lock();
+            if let Some(old) = self.timer {
+                timers.retain(|Reverse(timer)| timer.id != old);
+            }
+            timers.push(Reverse(timer));
+        });
+
+        self.timer = Some(id);
+    }
+
+    fn deregister(&mut self) {
+        if let Some(id) = self.timer.take() {
+            interrupts::without_interrupts(|| {
+                TIMERS.lock().retain(|Reverse(timer)| timer.id != id);
+            });
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        if Instant::now() >= self.deadline {
+            self.deregister();
+            return Poll::Ready(());
+        }
+
+        // the timer interrupt checks deadlines on every tick,
+        // so if the deadline passes after the check above we will still be woken on the next one.
+        self.register(ctx.waker());
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.deregister();
+    }
+}
+
+/// A stream that yields once every `period`
+#[must_use = "streams do nothing unless polled"]
+pub struct Interval {
+    period: Duration,
+    sleep: Sleep,
+}
+
+/// Create an [`Interval`] whose first tick completes after `period`.
+///
+/// If a tick is missed (because the task was not polled in time), the next tick is scheduled `period` after the late
+/// one instead of firing a burst of ticks to catch up.
+pub fn interval(period: Duration) -> Interval {
+    Interval {
+        period,
+        sleep: sleep(period),
+    }
+}
+
+impl Interval {
+    #[must_use]
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+}
+
+impl Stream for Interval {
+    /// The deadline of the tick that completed
+    type Item = Instant;
+
+    fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if Pin::new(&mut self.sleep).poll(ctx).is_pending() {
+            return Poll::Pending;
+        }
+
+        let deadline = self.sleep.deadline();
+        let now = Instant::now();
+        let mut next = deadline + self.period;
+        if next <= now {
+            next = now + self.period;
+        }
+        self.sleep.reset(next);
+
+        Poll::Ready(Some(deadline))
+    }
+}
+
+#[cfg(test)]
+fn is_registered(id: u64) -> bool {
+    interrupts::without_interrupts(|| TIMERS.lock().iter().any(|Reverse(timer)| timer.id == id))
+}
+
+#[test_case]
+fn test_timer_order() {
+    let now = Instant::now();
+    let waker = futures_util::task::noop_waker();
+    let mut timers = BinaryHeap::new();
+    for (millis, id) in [(30, 0), (10, 1), (20, 2), (10, 3)] {
+        timers.push(Reverse(Timer {
+            deadline: now + Duration::from_millis(millis),
+            id,
+            waker: waker.clone(),
+        }));
+    }
+
+    // earliest deadline first, then in the order they were registered
+    let order: alloc::vec::Vec<u64> = core::iter::from_fn(|| timers.pop())
+        .map(|Reverse(timer)| timer.id)
+        .collect();
+    assert_eq!(order, [1, 3, 2, 0]);
+}
+
+#[test_case]
+fn test_sleep_and_interval() {
+    let waker = futures_util::task::noop_waker();
+    let mut ctx = Context::from_waker(&waker);
+
+    let mut sleep = sleep(Duration::from_millis(2));
+    assert!(Pin::new(&mut sleep).poll(&mut ctx).is_pending());
+    let id = sleep.timer.expect("sleep did not register a timer");
+    assert!(is_registered(id));
+
+    // the timer interrupt wakes and removes the timer once the deadline passes
+    crate::time::delay(Duration::from_millis(3));
+    assert!(!is_registered(id));
+    assert!(Pin::new(&mut sleep).poll(&mut ctx).is_ready());
+
+    let mut interval = interval(Duration::from_millis(2));
+    assert!(Pin::new(&mut interval).poll_next(&mut ctx).is_pending());
+    let deadline = interval.sleep.deadline();
+    crate::time::delay(Duration::from_millis(3));
+    assert_eq!(
+        Pin::new(&mut interval).poll_next(&mut ctx),
+        Poll::Ready(Some(deadline))
+    );
+    // the next tick is scheduled a period later
+    assert!(interval.sleep.deadline() > deadline);
+}
@@ -0,0 +1,80 @@
+use core::arch::naked_asm;
+
+use alloc::boxed::Box;
+use x86_64::{instructions::interrupts, VirtAddr};
+
+/// A thread's entry point, as passed to [`entry`]
+pub(super) type Entry = Box<dyn FnOnce() + Send + 'static>;
+
+/// Amount of callee-saved registers [`switch`] pushes
+const SAVED_REGISTERS: usize = 6;
+
+/// Save the callee-saved registers on the current stack, store the stack pointer in `old_rsp`,
+/// then continue with the thread whose stack pointer is `new_rsp`.
+///
+/// Returns once another thread switches back to this one.
+///
+/// # Safety
+///
+/// - Interrupts must be disabled.
+/// - `new_rsp` must be a stack pointer saved by this function, or prepared by [`prepare_stack`].
+#[unsafe(naked)]
+pub(super) unsafe extern "C" fn switch(old_rsp: *mut u64, new_rsp: u64) {
+    naked_asm!(
+        "push rbp",
+        "push rbx",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        "mov [rdi], rsp",
+        "mov rsp, rsi",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop rbx",
+        "pop rbp",
+        "ret",
+    );
+}
+
+/// Where a new thread's first [`switch`] returns to. Moves the entry point [`prepare_stack`] left in r12
+/// into the first argument and calls [`entry`].
+#[unsafe(naked)]
+unsafe extern "C" fn trampoline() -> ! {
+    naked_asm!("mov rdi, r12", "call {entry}", "ud2", entry = sym entry);
+}
+
+extern "C" fn entry(entry: *mut Entry) -> ! {
+    let entry = unsafe { Box::from_raw(entry) };
+
+    // threads are switched to with interrupts disabled
+    interrupts::enable();
+    entry();
+
+    super::exit();
+}
+
+/// Set up the stack ending at `top` so that [`switch`]ing to it runs `entry`.
+///
+/// Returns the stack pointer to switch to.
+///
+/// # Safety
+///
+/// `top` must be the 16 byte aligned end of a mapped stack with room for at least 7 words.
+pub(super) unsafe fn prepare_stack(top: VirtAddr, entry: Entry) -> u64 {
+    let entry = Box::into_raw(Box::new(entry));
+
+    let top: *mut u64 = top.as_mut_ptr();
+    // `trampoline` is returned to with the stack 16 byte aligned, so its call leaves it aligned like the abi expects
+    let ret = top.sub(1);
+    ret.write(trampoline as *const () as u64);
+
+    // r15, r14, r13, r12, rbx, rbp in the order `switch` pops them
+    let registers = ret.sub(SAVED_REGISTERS);
+    registers.write_bytes(0, SAVED_REGISTERS);
+    registers.add(3).write(entry as u64);
+
+    registers as u64
+}
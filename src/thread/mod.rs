@@ -0,0 +1,275 @@
+mod context;
+pub mod scheduler;
+
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use log::trace;
+use spin::Mutex;
+use x86_64::instructions::{self, interrupts};
+
+use crate::{
+    memory::{
+        address_space::AddressSpace,
+        stack::{KernelStack, DEFAULT_STACK_SIZE},
+        vm::VmError,
+    },
+    time::{self, Duration, Instant},
+};
+pub use scheduler::State;
+use scheduler::{Thread, SCHEDULER};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ThreadId(u64);
+
+impl ThreadId {
+    fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Start scheduling threads, turning the code calling this into the first thread.
+///
+/// Must be called after the heap is initialised.
+///
+/// # Errors
+///
+/// Will error if allocating the idle thread's stack fails.
+///
+/// # Panics
+///
+/// Will panic if called more than once.
+pub fn init() -> Result<(), VmError> {
+    trace!("initialising scheduler");
+    let current = Thread::new(ThreadId::new(), 0, None, None);
+    let idle = new_thread(
+        ThreadId::new(),
+        Box::new(|| {
+            idle();
+        }),
+        None,
+    )?;
+
+    interrupts::without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        assert!(scheduler.is_none(), "scheduler initialised twice");
+        *scheduler = Some(scheduler::Scheduler::new(current, idle));
+    });
+
+    Ok(())
+}
+
+/// Create a thread with its own stack that starts running `entry` when first switched to
+fn new_thread(
+    id: ThreadId,
+    entry: context::Entry,
+    address_space: Option<Arc<AddressSpace>>,
+) -> Result<Thread, VmError> {
+    let stack = KernelStack::new(DEFAULT_STACK_SIZE)?;
+    let rsp = unsafe { context::prepare_stack(stack.top(), entry) };
+
+    Ok(Thread::new(id, rsp, Some(stack), address_space))
+}
+
+/// Runs when every other thread is waiting
+fn idle() -> ! {
+    loop {
+        reap();
+        instructions::hlt();
+    }
+}
+
+/// Drop the threads that exited, freeing their stacks.
+///
+/// A thread cannot free its own stack, so this happens later from another thread.
+fn reap() {
+    loop {
+        let thread = interrupts::without_interrupts(|| {
+            SCHEDULER
+                .lock()
+                .as_mut()
+                .and_then(scheduler::Scheduler::take_finished)
+        });
+        let Some(thread) = thread else {
+            break;
+        };
+        // outside the lock, since freeing the stack needs the vm locks
+        drop(thread);
+    }
+}
+
+/// Owns the right to wait for a thread to exit, see [`spawn_thread`]
+pub struct JoinHandle<T> {
+    id: ThreadId,
+    result: Arc<Mutex<Option<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    #[must_use]
+    pub fn id(&self) -> ThreadId {
+        self.id
+    }
+
+    /// Whether the thread has exited
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        interrupts::without_interrupts(|| {
+            !SCHEDULER
+                .lock()
+                .as_ref()
+                .is_some_and(|s| s.is_alive(self.id))
+        })
+    }
+
+    /// Wait for the thread to exit and return what it returned.
+    ///
+    /// Returns `None` if the thread exited without returning, like a user program calling the exit syscall.
+    #[allow(clippy::must_use_candidate)]
+    pub fn join(self) -> Option<T> {
+        loop {
+            if let Some(result) = self.result.lock().take() {
+                return Some(result);
+            }
+
+            let alive = interrupts::without_interrupts(|| {
+                let scheduler = SCHEDULER.lock();
+                // the result is stored before the thread exits, so it is ready if the thread is gone
+                if !scheduler.as_ref().is_some_and(|s| s.is_alive(self.id)) {
+                    return false;
+                }
+                unsafe { scheduler::reschedule(scheduler, State::Joining(self.id)) };
+                true
+            });
+
+            if !alive {
+                return self.result.lock().take();
+            }
+        }
+    }
+}
+
+/// Start running `f` on a new thread, which is preempted to share the cpu with every other thread.
+///
+/// # Errors
+///
+/// Will error if allocating the thread's stack fails.
+///
+/// # Panics
+///
+/// Will panic if [`init`] was not called.
+pub fn spawn_thread<F, T>(f: F) -> Result<JoinHandle<T>, VmError>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    spawn(f, None)
+}
+
+/// Like [`spawn_thread`], but the thread runs with `address_space` active, so that it can run user code mapped in it.
+///
+/// # Errors
+///
+/// Will error if allocating the thread's stack fails.
+///
+/// # Panics
+///
+/// Will panic if [`init`] was not called.
+pub fn spawn_thread_in<F, T>(
+    address_space: Arc<AddressSpace>,
+    f: F,
+) -> Result<JoinHandle<T>, VmError>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    spawn(f, Some(address_space))
+}
+
+fn spawn<F, T>(f: F, address_space: Option<Arc<AddressSpace>>) -> Result<JoinHandle<T>, VmError>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    reap();
+
+    let id = ThreadId::new();
+    let result = Arc::new(Mutex::new(None));
+
+    let thread_result = result.clone();
+    let entry = Box::new(move || {
+        let value = f();
+        *thread_result.lock() = Some(value);
+    });
+    let thread = new_thread(id, entry, address_space)?;
+
+    interrupts::without_interrupts(|| {
+        SCHEDULER
+            .lock()
+            .as_mut()
+            .expect("scheduler not initialised")
+            .add(thread);
+    });
+
+    Ok(JoinHandle { id, result })
+}
+
+/// Get the id of the running thread, or `None` if [`init`] was not called
+#[must_use]
+pub fn current() -> Option<ThreadId> {
+    interrupts::without_interrupts(|| {
+        SCHEDULER
+            .lock()
+            .as_ref()
+            .map(scheduler::Scheduler::current_id)
+    })
+}
+
+/// Get the id and state of every thread, or nothing if [`init`] was not called
+#[must_use]
+pub fn threads() -> Vec<(ThreadId, State)> {
+    interrupts::without_interrupts(|| {
+        SCHEDULER
+            .lock()
+            .as_ref()
+            .map(|scheduler| scheduler.threads().collect())
+            .unwrap_or_default()
+    })
+}
+
+/// Let other threads run before continuing
+pub fn yield_now() {
+    interrupts::without_interrupts(|| {
+        let scheduler = SCHEDULER.lock();
+        unsafe { scheduler::reschedule(scheduler, State::Ready) };
+    });
+}
+
+/// Block the current thread for at least `duration`, letting other threads run in the meantime.
+///
+/// Halts instead if [`init`] was not called.
+pub fn sleep(duration: Duration) {
+    let deadline = Instant::now() + duration;
+
+    let slept = interrupts::without_interrupts(|| {
+        let scheduler = SCHEDULER.lock();
+        if scheduler.is_none() {
+            return false;
+        }
+        unsafe { scheduler::reschedule(scheduler, State::Sleeping(deadline.ticks())) };
+        true
+    });
+
+    if !slept {
+        time::delay(duration);
+    }
+}
+
+/// Exit the current thread without returning from its entry point
+pub(crate) fn exit() -> ! {
+    interrupts::disable();
+    let scheduler = SCHEDULER.lock();
+    unsafe { scheduler::reschedule(scheduler, State::Finished) };
+
+    unreachable!("finished thread was switched back to");
+}
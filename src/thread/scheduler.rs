@@ -0,0 +1,213 @@
+use core::mem;
+
+use alloc::{boxed::Box, collections::VecDeque, sync::Arc};
+use spin::{Mutex, MutexGuard};
+
+use super::{context, ThreadId};
+use crate::{
+    gdt,
+    memory::{
+        self,
+        address_space::{self, AddressSpace},
+        stack::KernelStack,
+    },
+    time,
+};
+
+/// How many ticks a thread may run before it is preempted
+pub const TIME_SLICE: u64 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Ready,
+    Running,
+    /// Waiting until the tick count reaches the deadline
+    Sleeping(u64),
+    /// Waiting until the thread exits
+    Joining(ThreadId),
+    Finished,
+}
+
+pub(super) struct Thread {
+    id: ThreadId,
+    /// Saved stack pointer, only valid while the thread is not running
+    rsp: u64,
+    state: State,
+    /// `None` for the thread the kernel booted on, whose stack came from the bootloader
+    stack: Option<KernelStack>,
+    /// `None` for threads that only run in the kernel's address space
+    address_space: Option<Arc<AddressSpace>>,
+}
+
+impl Thread {
+    pub(super) fn new(
+        id: ThreadId,
+        rsp: u64,
+        stack: Option<KernelStack>,
+        address_space: Option<Arc<AddressSpace>>,
+    ) -> Self {
+        Self {
+            id,
+            rsp,
+            state: State::Ready,
+            stack,
+            address_space,
+        }
+    }
+}
+
+pub(super) struct Scheduler {
+    current: Box<Thread>,
+    /// Runs when no other thread is ready, `None` while it is the current thread
+    idle: Option<Box<Thread>>,
+    idle_id: ThreadId,
+    /// Every thread except the current and idle thread, in round-robin order
+    threads: VecDeque<Box<Thread>>,
+    /// Tick count when the current thread started running
+    slice_start: u64,
+}
+
+impl Scheduler {
+    pub(super) fn new(current: Thread, idle: Thread) -> Self {
+        let mut current = Box::new(current);
+        current.state = State::Running;
+
+        Self {
+            current,
+            idle_id: idle.id,
+            idle: Some(Box::new(idle)),
+            threads: VecDeque::new(),
+            slice_start: time::ticks(),
+        }
+    }
+
+    pub(super) fn current_id(&self) -> ThreadId {
+        self.current.id
+    }
+
+    pub(super) fn add(&mut self, thread: Thread) {
+        self.threads.push_back(Box::new(thread));
+    }
+
+    /// Whether `id` belongs to a thread that has not exited yet
+    pub(super) fn is_alive(&self, id: ThreadId) -> bool {
+        self.current.id == id
+            || self
+                .threads
+                .iter()
+                .any(|t| t.id == id && t.state != State::Finished)
+    }
+
+    /// The id and state of every thread, starting with the current one
+    pub(super) fn threads(&self) -> impl Iterator<Item = (ThreadId, State)> + '_ {
+        let current = core::iter::once(&self.current);
+        current
+            .chain(&self.idle)
+            .chain(&self.threads)
+            .map(|t| (t.id, t.state))
+    }
+
+    /// Remove a thread that exited, so that it can be dropped outside the lock
+    pub(super) fn take_finished(&mut self) -> Option<Box<Thread>> {
+        let index = self
+            .threads
+            .iter()
+            .position(|t| t.state == State::Finished)?;
+        self.threads.remove(index)
+    }
+
+    /// Whether the current thread has used up its time slice
+    fn slice_expired(&self) -> bool {
+        self.current.id == self.idle_id || time::ticks() - self.slice_start >= TIME_SLICE
+    }
+
+    /// Pick the next thread to run and make it current, leaving the previous one in `state`.
+    ///
+    /// Returns where to save the previous thread's stack pointer and the stack pointer to switch to,
+    /// or `None` if the current thread should keep running.
+    fn next(&mut self, state: State) -> Option<(*mut u64, u64)> {
+        let now = time::ticks();
+        for thread in &mut self.threads {
+            if matches!(thread.state, State::Sleeping(deadline) if deadline <= now) {
+                thread.state = State::Ready;
+            }
+        }
+
+        if state == State::Finished {
+            let id = self.current.id;
+            for thread in &mut self.threads {
+                if thread.state == State::Joining(id) {
+                    thread.state = State::Ready;
+                }
+            }
+        }
+
+        let next = match self.threads.iter().position(|t| t.state == State::Ready) {
+            Some(index) => self.threads.remove(index)?,
+            None if state == State::Ready => return None,
+            None => self.idle.take()?,
+        };
+
+        let mut previous = mem::replace(&mut self.current, next);
+        previous.state = state;
+        self.current.state = State::Running;
+        self.slice_start = now;
+
+        // interrupts and syscalls from user mode must land on the stack of the thread that was running
+        if let Some(stack) = &self.current.stack {
+            gdt::set_kernel_stack(stack.top());
+        }
+        let p4 = self
+            .current
+            .address_space
+            .as_ref()
+            .map_or_else(memory::kernel_p4, |space| space.p4_frame());
+        // kernel code and stacks are mapped the same in every address space
+        unsafe { address_space::activate_frame(p4) };
+
+        // the thread is boxed, so this stays valid after moving the box
+        let old_rsp = &raw mut previous.rsp;
+        let new_rsp = self.current.rsp;
+
+        if previous.id == self.idle_id {
+            self.idle = Some(previous);
+        } else {
+            self.threads.push_back(previous);
+        }
+
+        Some((old_rsp, new_rsp))
+    }
+}
+
+pub(super) static SCHEDULER: Mutex<Option<Scheduler>> = Mutex::new(None);
+
+/// Switch to the next thread, leaving the current one in `state`. Returns once the current thread runs again,
+/// or immediately if there is nothing else to run and `state` is [`State::Ready`].
+///
+/// # Safety
+///
+/// Interrupts must be disabled.
+pub(super) unsafe fn reschedule(mut scheduler: MutexGuard<Option<Scheduler>>, state: State) {
+    let Some(switch) = scheduler.as_mut().and_then(|s| s.next(state)) else {
+        return;
+    };
+    // the next thread may never give the lock back otherwise
+    drop(scheduler);
+
+    let (old_rsp, new_rsp) = switch;
+    context::switch(old_rsp, new_rsp);
+}
+
+/// Switch to the next thread if the current one used up its time slice. Called by the timer interrupt handler.
+pub(crate) fn preempt() {
+    // the interrupted code may hold the lock, in which case it just keeps running
+    let Some(scheduler) = SCHEDULER.try_lock() else {
+        return;
+    };
+    if !scheduler.as_ref().is_some_and(Scheduler::slice_expired) {
+        return;
+    }
+
+    // interrupt handlers run with interrupts disabled
+    unsafe { reschedule(scheduler, State::Ready) };
+}
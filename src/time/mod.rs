@@ -0,0 +1,197 @@
+pub mod pit;
+
+use core::{
+    fmt,
+    ops::{Add, AddAssign, Sub, SubAssign},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+pub use core::time::Duration;
+
+use log::trace;
+use x86_64::instructions;
+
+/// How many times per second the timer interrupt fires
+pub const TICK_RATE: u32 = 1000;
+
+/// The reload value actually programmed into the PIT.
+///
+/// Since the PIT can only divide its base frequency by an integer, the real tick rate is slightly off from [`TICK_RATE`].
+/// All conversions between ticks and [`Duration`]s use this value so that time does not drift.
+const RELOAD: u16 = pit::reload_value(TICK_RATE);
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Program the PIT to fire the timer interrupt at [`TICK_RATE`]
+pub fn init() {
+    trace!("setting pit reload value to {RELOAD}");
+    unsafe { pit::set_reload_value(RELOAD) };
+}
+
+/// Called by the timer interrupt handler once per tick
+pub(crate) fn tick() {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Get the number of timer ticks since [`init`]
+#[must_use]
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Get the time elapsed since [`init`]
+#[must_use]
+pub fn uptime() -> Duration {
+    ticks_to_duration(ticks())
+}
+
+/// Halt until at least `duration` has passed.
+///
+/// Interrupts must be enabled, otherwise this never returns.
+pub fn delay(duration: Duration) {
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+        instructions::hlt();
+    }
+}
+
+fn reload_period() -> u128 {
+    if RELOAD == 0 {
+        u128::from(u16::MAX) + 1
+    } else {
+        u128::from(RELOAD)
+    }
+}
+
+fn ticks_to_duration(ticks: u64) -> Duration {
+    let nanos =
+        u128::from(ticks) * reload_period() * NANOS_PER_SEC / u128::from(pit::BASE_FREQUENCY);
+    let secs = nanos / NANOS_PER_SEC;
+
+    #[allow(clippy::cast_possible_truncation, reason = "nanos % 1e9 always fits")]
+    let subsec_nanos = (nanos % NANOS_PER_SEC) as u32;
+
+    Duration::new(u64::try_from(secs).unwrap_or(u64::MAX), subsec_nanos)
+}
+
+/// Convert `duration` into ticks, rounding up
+fn duration_to_ticks(duration: Duration) -> u64 {
+    let ticks_per_sec_scaled = reload_period() * NANOS_PER_SEC;
+    let scaled = duration.as_nanos() * u128::from(pit::BASE_FREQUENCY);
+
+    u64::try_from(scaled.div_ceil(ticks_per_sec_scaled)).unwrap_or(u64::MAX)
+}
+
+/// A point in time, measured in timer ticks since [`init`]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(u64);
+
+impl Instant {
+    #[must_use]
+    pub fn now() -> Self {
+        Self(ticks())
+    }
+
+    /// Get the amount of ticks since [`init`] this instant represents
+    #[must_use]
+    pub const fn ticks(self) -> u64 {
+        self.0
+    }
+
+    /// Get the time elapsed since this instant, see [`Instant::duration_since`]
+    #[must_use]
+    pub fn elapsed(self) -> Duration {
+        Self::now().duration_since(self)
+    }
+
+    /// Get the time elapsed from `earlier` to this instant, or zero if `earlier` is later than this instant
+    #[must_use]
+    pub fn duration_since(self, earlier: Self) -> Duration {
+        self.checked_duration_since(earlier).unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn checked_duration_since(self, earlier: Self) -> Option<Duration> {
+        self.0.checked_sub(earlier.0).map(ticks_to_duration)
+    }
+
+    #[must_use]
+    pub fn checked_add(self, duration: Duration) -> Option<Self> {
+        self.0.checked_add(duration_to_ticks(duration)).map(Self)
+    }
+
+    #[must_use]
+    pub fn checked_sub(self, duration: Duration) -> Option<Self> {
+        self.0.checked_sub(duration_to_ticks(duration)).map(Self)
+    }
+}
+
+impl fmt::Debug for Instant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Instant({:?})", ticks_to_duration(self.0))
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    /// # Panics
+    ///
+    /// Will panic if the result overflows. See [`Instant::checked_add`] for a version without panics.
+    fn add(self, rhs: Duration) -> Self::Output {
+        self.checked_add(rhs)
+            .expect("overflow when adding duration to instant")
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, rhs: Duration) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    /// # Panics
+    ///
+    /// Will panic if the result underflows. See [`Instant::checked_sub`] for a version without panics.
+    fn sub(self, rhs: Duration) -> Self::Output {
+        self.checked_sub(rhs)
+            .expect("overflow when subtracting duration from instant")
+    }
+}
+
+impl SubAssign<Duration> for Instant {
+    fn sub_assign(&mut self, rhs: Duration) {
+        *self = *self - rhs;
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, rhs: Instant) -> Self::Output {
+        self.duration_since(rhs)
+    }
+}
+
+#[test_case]
+fn test_tick_conversion() {
+    let one_sec = duration_to_ticks(Duration::from_secs(1));
+    assert!(one_sec.abs_diff(u64::from(TICK_RATE)) <= 1);
+
+    // rounding up should never give back a shorter duration
+    for millis in [1, 7, 250, 1000, 12_345] {
+        let duration = Duration::from_millis(millis);
+        assert!(ticks_to_duration(duration_to_ticks(duration)) >= duration);
+    }
+
+    let start = Instant(10);
+    let later = start + Duration::from_millis(100);
+    assert!(later > start);
+    assert_eq!(later - Duration::from_millis(100), start);
+    assert_eq!(start - later, Duration::ZERO);
+}
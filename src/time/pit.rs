@@ -0,0 +1,41 @@
+use x86_64::instructions::port::Port;
+
+/// Frequency of the PIT's oscillator, in hz
+pub const BASE_FREQUENCY: u32 = 1_193_182;
+
+const CHANNEL_0: u16 = 0x40;
+const COMMAND: u16 = 0x43;
+
+/// Get the reload value that makes the PIT fire at (roughly) `frequency` hz
+///
+/// A reload value of 0 is treated as 65536 by the PIT, so frequencies that are too low are clamped to the slowest rate.
+#[must_use]
+pub const fn reload_value(frequency: u32) -> u16 {
+    let divisor = BASE_FREQUENCY / frequency;
+    if divisor > u16::MAX as u32 {
+        0
+    } else if divisor < 1 {
+        1
+    } else {
+        #[allow(clippy::cast_possible_truncation, reason = "checked above")]
+        let divisor = divisor as u16;
+        divisor
+    }
+}
+
+/// Program channel 0 (connected to IRQ0) as a rate generator with the given reload value.
+///
+/// # Safety
+///
+/// Caller must guarantee nothing else relies on the current PIT configuration.
+pub unsafe fn set_reload_value(reload: u16) {
+    let mut command: Port<u8> = Port::new(COMMAND);
+    let mut channel_0: Port<u8> = Port::new(CHANNEL_0);
+
+    let [low, high] = reload.to_le_bytes();
+
+    // channel 0, lobyte/hibyte access, mode 2 (rate generator), binary mode
+    command.write(0b0011_0100);
+    channel_0.write(low);
+    channel_0.write(high);
+}
@@ -0,0 +1,316 @@
+//! A parser for the ANSI/VT100 escape sequences that terminals understand
+
+/// How many parameters of a control sequence are kept, any more are ignored
+const MAX_PARAMS: usize = 16;
+
+const ESC: u8 = 0x1b;
+
+/// What a byte of output asks the screen to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Show a character
+    Print(u8),
+    /// Move to the start of the next line, scrolling if on the last one
+    LineFeed,
+    CarriageReturn,
+    /// Move to the next tab stop
+    Tab,
+    /// Move one column left, without erasing anything
+    Backspace,
+    CursorUp(usize),
+    CursorDown(usize),
+    CursorForward(usize),
+    CursorBack(usize),
+    /// Move down this many rows, to the start of the row
+    NextLine(usize),
+    /// Move up this many rows, to the start of the row
+    PreviousLine(usize),
+    /// Move to this column, counting from 0
+    CursorColumn(usize),
+    /// Move to this row and column, counting from 0
+    CursorPosition {
+        row: usize,
+        column: usize,
+    },
+    EraseDisplay(Erase),
+    EraseLine(Erase),
+    SaveCursor,
+    RestoreCursor,
+    ShowCursor(bool),
+    /// Select graphic rendition, one per attribute of the sequence
+    Sgr(Sgr),
+}
+
+/// Which part of the screen or line to erase
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Erase {
+    /// From the cursor to the end
+    ToEnd,
+    /// From the start up to and including the cursor
+    ToStart,
+    All,
+    /// Only for [`Action::EraseDisplay`], the scrollback history
+    Scrollback,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sgr {
+    /// Back to the default colors, without bold or reverse
+    Reset,
+    Bold(bool),
+    /// Swap the foreground and background colors
+    Reverse(bool),
+    /// One of the 16 ANSI colors, black, red, green, yellow, blue, magenta, cyan and white, then their bright versions
+    Foreground(u8),
+    /// One of the 16 ANSI colors, see [`Sgr::Foreground`]
+    Background(u8),
+    DefaultForeground,
+    DefaultBackground,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    /// After an escape byte
+    Escape,
+    /// In a control sequence, after `ESC [`
+    Csi,
+}
+
+/// Turns a stream of bytes into [`Action`]s
+#[derive(Debug, Clone)]
+pub struct Parser {
+    state: State,
+    params: [u16; MAX_PARAMS],
+    /// How many parameters were started
+    len: usize,
+    /// Whether the sequence started with `?`, for private modes like showing the cursor
+    private: bool,
+}
+
+impl Parser {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            state: State::Ground,
+            params: [0; MAX_PARAMS],
+            len: 0,
+            private: false,
+        }
+    }
+
+    /// Feed one byte, calling `f` with every action it completes.
+    ///
+    /// Unknown sequences and control characters are dropped.
+    pub fn advance(&mut self, byte: u8, mut f: impl FnMut(Action)) {
+        match self.state {
+            State::Ground => match byte {
+                ESC => self.state = State::Escape,
+                b'\n' => f(Action::LineFeed),
+                b'\r' => f(Action::CarriageReturn),
+                b'\t' => f(Action::Tab),
+                0x08 => f(Action::Backspace),
+                0x00..=0x1f | 0x7f => {}
+                _ => f(Action::Print(byte)),
+            },
+            State::Escape => {
+                self.state = State::Ground;
+                match byte {
+                    b'[' => {
+                        self.state = State::Csi;
+                        self.params = [0; MAX_PARAMS];
+                        self.len = 0;
+                        self.private = false;
+                    }
+                    b'7' => f(Action::SaveCursor),
+                    b'8' => f(Action::RestoreCursor),
+                    _ => {}
+                }
+            }
+            State::Csi => match byte {
+                b'0'..=b'9' => {
+                    if self.len == 0 {
+                        self.len = 1;
+                    }
+                    let param = &mut self.params[self.len - 1];
+                    *param = param
+                        .saturating_mul(10)
+                        .saturating_add(u16::from(byte - b'0'));
+                }
+                b';' => {
+                    self.len = (self.len.max(1) + 1).min(MAX_PARAMS);
+                }
+                b'?' => self.private = true,
+                // the final byte
+                0x40..=0x7e => {
+                    self.state = State::Ground;
+                    self.dispatch(byte, f);
+                }
+                ESC => self.state = State::Escape,
+                // intermediate bytes, which none of the supported sequences have
+                _ => {}
+            },
+        }
+    }
+
+    /// Parameter `i`, or `default` if it was left out or 0
+    fn param(&self, i: usize, default: usize) -> usize {
+        match self.params[..self.len].get(i) {
+            Some(&param) if param != 0 => usize::from(param),
+            _ => default,
+        }
+    }
+
+    fn erase(&self) -> Option<Erase> {
+        match self.param(0, 0) {
+            0 => Some(Erase::ToEnd),
+            1 => Some(Erase::ToStart),
+            2 => Some(Erase::All),
+            3 => Some(Erase::Scrollback),
+            _ => None,
+        }
+    }
+
+    fn dispatch(&self, byte: u8, mut f: impl FnMut(Action)) {
+        if self.private {
+            if self.param(0, 0) == 25 && matches!(byte, b'h' | b'l') {
+                f(Action::ShowCursor(byte == b'h'));
+            }
+            return;
+        }
+
+        let n = self.param(0, 1);
+        let action = match byte {
+            b'A' => Action::CursorUp(n),
+            b'B' => Action::CursorDown(n),
+            b'C' => Action::CursorForward(n),
+            b'D' => Action::CursorBack(n),
+            b'E' => Action::NextLine(n),
+            b'F' => Action::PreviousLine(n),
+            b'G' => Action::CursorColumn(n - 1),
+            b'H' | b'f' => Action::CursorPosition {
+                row: n - 1,
+                column: self.param(1, 1) - 1,
+            },
+            b'J' => match self.erase() {
+                Some(erase) => Action::EraseDisplay(erase),
+                None => return,
+            },
+            b'K' => match self.erase() {
+                Some(erase @ (Erase::ToEnd | Erase::ToStart | Erase::All)) => {
+                    Action::EraseLine(erase)
+                }
+                _ => return,
+            },
+            b's' => Action::SaveCursor,
+            b'u' => Action::RestoreCursor,
+            b'm' => return self.sgr(f),
+            _ => return,
+        };
+
+        f(action);
+    }
+
+    fn sgr(&self, mut f: impl FnMut(Action)) {
+        // no parameters is a reset
+        let params = &self.params[..self.len.max(1)];
+
+        let mut i = 0;
+        while let Some(&param) = params.get(i) {
+            i += 1;
+
+            #[allow(clippy::cast_possible_truncation, reason = "the ranges are small")]
+            let sgr = match param {
+                0 => Sgr::Reset,
+                1 => Sgr::Bold(true),
+                22 => Sgr::Bold(false),
+                7 => Sgr::Reverse(true),
+                27 => Sgr::Reverse(false),
+                30..=37 => Sgr::Foreground((param - 30) as u8),
+                39 => Sgr::DefaultForeground,
+                40..=47 => Sgr::Background((param - 40) as u8),
+                49 => Sgr::DefaultBackground,
+                90..=97 => Sgr::Foreground((param - 90 + 8) as u8),
+                100..=107 => Sgr::Background((param - 100 + 8) as u8),
+                // 256 color and rgb colors, only the first 16 colors of which can be shown
+                38 | 48 => {
+                    let color = match params.get(i) {
+                        Some(5) => {
+                            i += 2;
+                            params.get(i - 1).copied()
+                        }
+                        Some(2) => {
+                            i += 4;
+                            None
+                        }
+                        _ => None,
+                    };
+                    match color {
+                        Some(color @ 0..=15) if param == 38 => Sgr::Foreground(color as u8),
+                        Some(color @ 0..=15) => Sgr::Background(color as u8),
+                        _ => continue,
+                    }
+                }
+                _ => continue,
+            };
+
+            f(Action::Sgr(sgr));
+        }
+    }
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+fn parse(bytes: &[u8]) -> alloc::vec::Vec<Action> {
+    let mut parser = Parser::new();
+    let mut actions = alloc::vec::Vec::new();
+    for &byte in bytes {
+        parser.advance(byte, |action| actions.push(action));
+    }
+    actions
+}
+
+#[test_case]
+fn test_parse_cursor_movement() {
+    assert_eq!(
+        parse(b"a\r\x1b[2A\x1b[C\x1b[5;10H\x1b[H"),
+        [
+            Action::Print(b'a'),
+            Action::CarriageReturn,
+            Action::CursorUp(2),
+            Action::CursorForward(1),
+            Action::CursorPosition { row: 4, column: 9 },
+            Action::CursorPosition { row: 0, column: 0 },
+        ]
+    );
+    assert_eq!(
+        parse(b"\x1b[2J\x1b[K\x1b[?25l"),
+        [
+            Action::EraseDisplay(Erase::All),
+            Action::EraseLine(Erase::ToEnd),
+            Action::ShowCursor(false),
+        ]
+    );
+    // unknown sequences are dropped entirely
+    assert_eq!(parse(b"\x1b[5ZX"), [Action::Print(b'X')]);
+}
+
+#[test_case]
+fn test_parse_sgr() {
+    assert_eq!(
+        parse(b"\x1b[1;31;42m\x1b[m\x1b[38;5;12;38;2;1;2;3;97m"),
+        [
+            Action::Sgr(Sgr::Bold(true)),
+            Action::Sgr(Sgr::Foreground(1)),
+            Action::Sgr(Sgr::Background(2)),
+            Action::Sgr(Sgr::Reset),
+            Action::Sgr(Sgr::Foreground(12)),
+            Action::Sgr(Sgr::Foreground(15)),
+        ]
+    );
+}
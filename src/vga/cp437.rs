@@ -0,0 +1,76 @@
+//! The characters of code page 437, the font of the vga text mode
+
+/// Shown for characters code page 437 does not have, ■
+pub const REPLACEMENT: u8 = 0xfe;
+
+/// The glyphs of bytes 0x00 to 0x1f, which are only shown for these characters since the ascii control characters
+/// have their usual meaning
+const LOW: [char; 32] = [
+    '\0', '☺', '☻', '♥', '♦', '♣', '♠', '•', '◘', '○', '◙', '♂', '♀', '♪', '♫', '☼', //
+    '►', '◄', '↕', '‼', '¶', '§', '▬', '↨', '↑', '↓', '→', '←', '∟', '↔', '▲', '▼', //
+];
+
+/// The glyphs of bytes 0x7f to 0xff
+const HIGH: [char; 129] = [
+    '⌂', //
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', //
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', //
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', //
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐', //
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧', //
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀', //
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', //
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}', //
+];
+
+/// Characters that look like a glyph meant for another one
+const ALIASES: [(char, u8); 6] = [
+    // greek small beta, for the german sharp s
+    ('\u{3b2}', 0xe1),
+    // n-ary summation, for greek capital sigma
+    ('\u{2211}', 0xe4),
+    // greek small mu, for the micro sign
+    ('\u{3bc}', 0xe6),
+    // ohm sign, for greek capital omega
+    ('\u{2126}', 0xea),
+    // greek phi symbol, for greek small phi
+    ('\u{3d5}', 0xed),
+    // element of, for greek small epsilon
+    ('\u{2208}', 0xee),
+];
+
+/// The byte that shows `c`, if code page 437 has it
+#[must_use]
+pub fn from_char(c: char) -> Option<u8> {
+    if matches!(c, ' '..='~') {
+        return u8::try_from(c).ok();
+    }
+
+    let position = |table: &[char]| table.iter().position(|&glyph| glyph == c);
+    #[allow(
+        clippy::cast_possible_truncation,
+        reason = "the tables are shorter than 256"
+    )]
+    if let Some(i) = position(&LOW) {
+        Some(i as u8)
+    } else if let Some(i) = position(&HIGH) {
+        Some(0x7f + i as u8)
+    } else {
+        ALIASES
+            .iter()
+            .find_map(|&(alias, byte)| (alias == c).then_some(byte))
+    }
+}
+
+#[test_case]
+fn test_from_char() {
+    assert_eq!(from_char('a'), Some(b'a'));
+    assert_eq!(from_char('☺'), Some(0x01));
+    assert_eq!(from_char('⌂'), Some(0x7f));
+    assert_eq!(from_char('é'), Some(0x82));
+    assert_eq!(from_char('─'), Some(0xc4));
+    assert_eq!(from_char('π'), Some(0xe3));
+    assert_eq!(from_char('β'), Some(0xe1));
+    assert_eq!(from_char('\u{a0}'), Some(0xff));
+    assert_eq!(from_char('€'), None);
+}
@@ -0,0 +1,62 @@
+//! The hardware text cursor, through the CRT controller's registers
+
+use x86_64::instructions::port::Port;
+
+/// Selects which register [`DATA_PORT`] accesses
+const INDEX_PORT: u16 = 0x3d4;
+const DATA_PORT: u16 = 0x3d5;
+
+const CURSOR_START: u8 = 0x0a;
+const CURSOR_END: u8 = 0x0b;
+const LOCATION_HIGH: u8 = 0x0e;
+const LOCATION_LOW: u8 = 0x0f;
+
+/// Bit of the cursor start register that hides the cursor
+const CURSOR_DISABLE: u8 = 1 << 5;
+/// The scanline bits of the cursor start and end registers, the other bits must be kept
+const SCANLINE_MASK: u8 = 0b1_1111;
+
+/// The scanlines of a character cell the cursor covers, from 0 at the top to 15 at the bottom
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CursorShape {
+    pub start: u8,
+    pub end: u8,
+}
+
+impl CursorShape {
+    /// The bottom two scanlines, what the BIOS usually sets up
+    pub const UNDERLINE: Self = Self { start: 14, end: 15 };
+    /// The whole character cell
+    pub const BLOCK: Self = Self { start: 0, end: 15 };
+}
+
+/// Move the cursor to the character at `index`, counting from the top left of the screen
+pub(super) fn set_position(index: u16) {
+    let [low, high] = index.to_le_bytes();
+    write(LOCATION_HIGH, high);
+    write(LOCATION_LOW, low);
+}
+
+/// Set the cursor's shape, and hide it if not `visible`
+pub(super) fn set_shape(shape: CursorShape, visible: bool) {
+    let disable = if visible { 0 } else { CURSOR_DISABLE };
+    let start = read(CURSOR_START) & !(SCANLINE_MASK | CURSOR_DISABLE);
+    write(CURSOR_START, start | shape.start & SCANLINE_MASK | disable);
+
+    let end = read(CURSOR_END) & !SCANLINE_MASK;
+    write(CURSOR_END, end | shape.end & SCANLINE_MASK);
+}
+
+fn read(register: u8) -> u8 {
+    unsafe {
+        Port::new(INDEX_PORT).write(register);
+        Port::new(DATA_PORT).read()
+    }
+}
+
+fn write(register: u8, value: u8) {
+    unsafe {
+        Port::new(INDEX_PORT).write(register);
+        Port::new(DATA_PORT).write(value);
+    }
+}
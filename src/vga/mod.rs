@@ -0,0 +1,1117 @@
+pub mod ansi;
+pub mod cp437;
+mod cursor;
+
+use alloc::collections::VecDeque;
+use core::{
+    fmt::{self, Write},
+    ops::{Deref, Range},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use conquer_once::spin::Lazy;
+use log::{Level, LevelFilter, Log};
+use spin::Mutex;
+use volatile::Volatile;
+
+use ansi::{Action, Erase, Sgr};
+pub use cursor::CursorShape;
+
+const VGA_BUFFER: *mut Buffer = 0xb8000 as *mut Buffer;
+
+/// How many virtual consoles there are, switched between with alt+f1 to alt+f6
+pub const CONSOLE_COUNT: usize = 6;
+
+/// The virtual consoles, each with its own screen, cursor and colors. Only the active one is shown.
+pub static CONSOLES: Lazy<[Mutex<Writer>; CONSOLE_COUNT]> = Lazy::new(|| {
+    let color_code = ColorCode::new(Color::Green, Color::Black);
+    let mut consoles = core::array::from_fn(|_| Writer::new(color_code));
+
+    // the first console starts out active, keeping whatever was already on the screen
+    let first = &mut consoles[0];
+    first.screen = core::array::from_fn(read_vga_row);
+    first.show();
+
+    consoles.map(Mutex::new)
+});
+
+/// The first console, which [`print!`] and the [`Logger`] write to
+pub static WRITER: Lazy<&Mutex<Writer>> = Lazy::new(|| &CONSOLES[0]);
+
+/// Index of the console in [`CONSOLES`] that is shown
+static ACTIVE_CONSOLE: AtomicUsize = AtomicUsize::new(0);
+
+// The consoles are locked separately, so the vga text buffer is never borrowed as a whole.
+// It is only accessed one character at a time through these.
+
+/// Read the character at `row` and `col` of the vga text buffer
+fn read_vga(row: usize, col: usize) -> Char {
+    unsafe { (*VGA_BUFFER).chars[row][col].read() }
+}
+
+/// Read a whole row of the vga text buffer
+fn read_vga_row(row: usize) -> Row {
+    core::array::from_fn(|col| read_vga(row, col))
+}
+
+/// Write `char` at `row` and `col` of the vga text buffer
+fn write_vga(row: usize, col: usize, char: Char) {
+    unsafe { (*VGA_BUFFER).chars[row][col].write(char) };
+}
+
+pub struct Logger {
+    pub verbosity: LevelFilter,
+}
+
+/// # Panics
+/// Will panic if a logger has already been set
+pub fn init_logger(logger: &'static Logger) {
+    log::set_logger(logger).expect("failed to initialize vga logger");
+    log::set_max_level(logger.verbosity);
+}
+
+impl Logger {
+    #[must_use]
+    pub const fn new(verbosity: LevelFilter) -> Self {
+        Self { verbosity }
+    }
+}
+
+impl Log for Logger {
+    // no way to flush vga
+    fn flush(&self) {}
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            crate::println_colored!(
+                level_color(record.level()),
+                "{}:{}->{}: {}",
+                record.module_path().unwrap(),
+                record.line().unwrap(),
+                record.level(),
+                record.args(),
+            );
+        }
+    }
+
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.verbosity
+    }
+}
+
+/// The color each level is logged in
+fn level_color(level: Level) -> Color {
+    match level {
+        Level::Error => Color::LightRed,
+        Level::Warn => Color::Yellow,
+        Level::Info => Color::LightGreen,
+        Level::Debug => Color::LightCyan,
+        Level::Trace => Color::DarkGray,
+    }
+}
+
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::vga::private_print(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
+}
+
+/// Like [`print!`], in a foreground color and optionally a background color, given before the format string
+#[macro_export]
+macro_rules! print_colored {
+    ($foreground:expr, $fmt:literal $($arg:tt)*) => (
+        $crate::vga::private_print_colored($foreground, None, format_args!($fmt $($arg)*))
+    );
+    ($foreground:expr, $background:expr, $fmt:literal $($arg:tt)*) => (
+        $crate::vga::private_print_colored($foreground, Some($background), format_args!($fmt $($arg)*))
+    );
+}
+
+/// Like [`println!`], in colors given as for [`print_colored!`]
+#[macro_export]
+macro_rules! println_colored {
+    ($foreground:expr, $fmt:literal $($arg:tt)*) => (
+        $crate::print_colored!($foreground, "{}\n", format_args!($fmt $($arg)*))
+    );
+    ($foreground:expr, $background:expr, $fmt:literal $($arg:tt)*) => (
+        $crate::print_colored!($foreground, $background, "{}\n", format_args!($fmt $($arg)*))
+    );
+}
+
+#[doc(hidden)]
+pub fn private_print(args: fmt::Arguments) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        WRITER.lock().write_fmt(args).unwrap();
+    });
+}
+
+#[doc(hidden)]
+pub fn private_print_colored(foreground: Color, background: Option<Color>, args: fmt::Arguments) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        match background {
+            Some(background) => writer.push_color(foreground, background),
+            None => writer.push_foreground(foreground),
+        }
+        writer.write_fmt(args).unwrap();
+        writer.pop_color();
+    });
+}
+
+/// Pops the color it pushed to [`WRITER`] once dropped, see [`push_color`]
+#[must_use = "the color is popped as soon as the guard is dropped"]
+pub struct ColorGuard {
+    _private: (),
+}
+
+impl Drop for ColorGuard {
+    fn drop(&mut self) {
+        x86_64::instructions::interrupts::without_interrupts(|| WRITER.lock().pop_color());
+    }
+}
+
+/// Write everything printed to [`WRITER`] in `foreground` on `background` until the guard is dropped
+pub fn push_color(foreground: Color, background: Color) -> ColorGuard {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        WRITER.lock().push_color(foreground, background);
+    });
+    ColorGuard { _private: () }
+}
+
+/// Write everything printed to [`WRITER`] in `foreground` until the guard is dropped
+pub fn push_foreground(foreground: Color) -> ColorGuard {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        WRITER.lock().push_foreground(foreground);
+    });
+    ColorGuard { _private: () }
+}
+
+#[repr(u8)]
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Black = 0,
+    Blue = 1,
+    Green = 2,
+    Cyan = 3,
+    Red = 4,
+    Magenta = 5,
+    Brown = 6,
+    LightGray = 7,
+    DarkGray = 8,
+    LightBlue = 9,
+    LightGreen = 10,
+    LightCyan = 11,
+    LightRed = 12,
+    Pink = 13,
+    Yellow = 14,
+    White = 15,
+}
+
+/// The vga colors for the 16 ANSI colors, in the order of their escape codes
+const ANSI_COLORS: [Color; 16] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Brown,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::LightGray,
+    Color::DarkGray,
+    Color::LightRed,
+    Color::LightGreen,
+    Color::Yellow,
+    Color::LightBlue,
+    Color::Pink,
+    Color::LightCyan,
+    Color::White,
+];
+
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorCode(u8);
+
+impl Deref for ColorCode {
+    type Target = u8;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl ColorCode {
+    #[must_use]
+    pub fn new(foreground: Color, background: Color) -> Self {
+        // first 4 bits is background, last 4 is foreground
+        // eg. for ColorCode 10100010, the bg is 1010 (10, light green), while the fg is 0010 (2, green)
+        Self((background as u8) << 4 | (foreground as u8))
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Char {
+    ascii_char: u8,
+    color_code: ColorCode,
+}
+
+pub const BUFFER_HEIGHT: usize = 25;
+pub const BUFFER_WIDTH: usize = 80;
+
+#[repr(transparent)]
+pub struct Buffer {
+    // volatile so compiler doesn't optimize away writes to `chars`
+    chars: [[Volatile<Char>; BUFFER_WIDTH]; BUFFER_HEIGHT],
+}
+
+/// One row of the screen
+type Row = [Char; BUFFER_WIDTH];
+
+/// Tab stops are every this many columns
+const TAB_WIDTH: usize = 8;
+
+/// How many colors [`Writer::push_color`] remembers, any deeper pushes are not popped back
+const COLOR_STACK_SIZE: usize = 16;
+
+/// Colors and attributes set with escape sequences
+#[derive(Debug, Clone, Copy)]
+struct Style {
+    /// The vga color, before bold makes it bright
+    foreground: u8,
+    background: u8,
+    bold: bool,
+    reverse: bool,
+}
+
+impl Style {
+    fn new(color_code: ColorCode) -> Self {
+        Self {
+            foreground: *color_code & 0xf,
+            background: *color_code >> 4,
+            bold: false,
+            reverse: false,
+        }
+    }
+
+    fn color_code(self) -> ColorCode {
+        let mut foreground = self.foreground;
+        if self.bold {
+            foreground |= 0x8;
+        }
+        if self.reverse {
+            ColorCode(foreground << 4 | self.background)
+        } else {
+            ColorCode(self.background << 4 | foreground)
+        }
+    }
+}
+
+/// How many rows that scrolled off the top of the screen are kept by default, see [`set_scrollback`]
+pub const DEFAULT_SCROLLBACK: usize = 500;
+
+pub struct Writer {
+    column_pos: usize,
+    /// The row the next character will be written to, the bottom row unless moved with [`Writer::set_cursor`]
+    row_pos: usize,
+    color_code: ColorCode,
+    /// Whether this is the console being shown, the only one that writes to the vga buffer
+    active: bool,
+    /// What the screen shows when scrolled to the bottom
+    screen: [Row; BUFFER_HEIGHT],
+    /// Rows that scrolled off the top of the screen, oldest first
+    history: VecDeque<Row>,
+    /// How many rows `history` may hold, it never grows past its capacity
+    scrollback: usize,
+    /// How many rows the view is scrolled up from the bottom
+    scroll: usize,
+    cursor_shape: CursorShape,
+    cursor_visible: bool,
+    /// Interprets escape sequences in what is written with [`Writer::write_str`]
+    parser: ansi::Parser,
+    /// The colors a reset escape sequence goes back to
+    default_color: ColorCode,
+    /// Makes up `color_code`
+    style: Style,
+    /// Where the cursor was saved by an escape sequence
+    saved_cursor: (usize, usize),
+    /// The styles to go back to with [`Writer::pop_color`], the first `color_depth` of which are used
+    color_stack: [Style; COLOR_STACK_SIZE],
+    /// How many colors are pushed, which can be more than are remembered
+    color_depth: usize,
+}
+
+impl fmt::Write for Writer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_str(s);
+        Ok(())
+    }
+}
+
+impl Writer {
+    /// A blank console that is not shown, writing only to its own screen
+    #[must_use]
+    pub fn new(color_code: ColorCode) -> Self {
+        let blank = Char {
+            ascii_char: b' ',
+            color_code,
+        };
+
+        Self {
+            column_pos: 0,
+            row_pos: BUFFER_HEIGHT - 1,
+            color_code,
+            active: false,
+            screen: [[blank; BUFFER_WIDTH]; BUFFER_HEIGHT],
+            history: VecDeque::new(),
+            scrollback: 0,
+            scroll: 0,
+            cursor_shape: CursorShape::UNDERLINE,
+            cursor_visible: true,
+            parser: ansi::Parser::new(),
+            default_color: color_code,
+            style: Style::new(color_code),
+            saved_cursor: (BUFFER_HEIGHT - 1, 0),
+            color_stack: [Style::new(color_code); COLOR_STACK_SIZE],
+            color_depth: 0,
+        }
+    }
+}
+
+impl Writer {
+    /// Write `s`, interpreting ANSI escape sequences and `\r`, `\t` and backspace in it.
+    ///
+    /// Other characters are shown with their code page 437 glyph, or ■ if there is none.
+    pub fn write_str(&mut self, s: &str) {
+        self.scroll_to_bottom();
+
+        // taken out so that its actions can be applied to the writer
+        let mut parser = core::mem::take(&mut self.parser);
+        for c in s.chars() {
+            match u8::try_from(c) {
+                Ok(byte) if byte.is_ascii() => parser.advance(byte, |action| self.apply(action)),
+                _ => self.apply(Action::Print(glyph(c))),
+            }
+        }
+        self.parser = parser;
+
+        self.update_cursor();
+    }
+
+    /// Write a single code page 437 character, which is shown as is instead of being interpreted, except for `\n`
+    pub fn write_byte(&mut self, byte: u8) {
+        self.put_byte(byte);
+        self.update_cursor();
+    }
+
+    /// Write `byte` without moving the hardware cursor, which is slow
+    fn put_byte(&mut self, byte: u8) {
+        if byte == b'\n' {
+            self.scroll_to_bottom();
+            self.new_line();
+        } else {
+            self.put_glyph(byte);
+        }
+    }
+
+    /// Write the glyph `byte` without interpreting it, not even `\n`, and without moving the hardware cursor
+    fn put_glyph(&mut self, byte: u8) {
+        self.scroll_to_bottom();
+
+        if self.column_pos >= BUFFER_WIDTH {
+            self.new_line();
+        }
+
+        let row = self.row_pos;
+        let col = self.column_pos;
+
+        self.write_char(
+            row,
+            col,
+            Char {
+                ascii_char: byte,
+                color_code: self.color_code,
+            },
+        );
+        self.column_pos += 1;
+    }
+
+    pub fn backspace(&mut self) {
+        if self.column_pos == 0 {
+            return;
+        }
+
+        self.scroll_to_bottom();
+        self.column_pos -= 1;
+        self.write_char(
+            self.row_pos,
+            self.column_pos,
+            Char {
+                ascii_char: 0x0,
+                color_code: ColorCode::new(Color::Black, Color::Black),
+            },
+        );
+        self.update_cursor();
+    }
+
+    /// The column the next character will be written to, on the cursor's row
+    #[must_use]
+    pub fn column(&self) -> usize {
+        self.column_pos
+    }
+
+    /// Move where the next character will be written to within the cursor's row, clamped to its width
+    pub fn set_column(&mut self, column: usize) {
+        self.column_pos = column.min(BUFFER_WIDTH);
+        self.update_cursor();
+    }
+
+    /// The row and column the next character will be written to
+    #[must_use]
+    pub fn cursor_position(&self) -> (usize, usize) {
+        (self.row_pos, self.column_pos)
+    }
+
+    /// Move where the next character will be written to, clamped to the screen.
+    ///
+    /// Output keeps going from there, wrapping onto the next rows and only scrolling once it reaches the bottom.
+    pub fn set_cursor(&mut self, row: usize, column: usize) {
+        self.row_pos = row.min(BUFFER_HEIGHT - 1);
+        self.set_column(column);
+    }
+
+    #[must_use]
+    pub fn cursor_shape(&self) -> CursorShape {
+        self.cursor_shape
+    }
+
+    pub fn set_cursor_shape(&mut self, shape: CursorShape) {
+        self.cursor_shape = shape;
+        self.update_cursor_shape();
+    }
+
+    #[must_use]
+    pub fn cursor_visible(&self) -> bool {
+        self.cursor_visible
+    }
+
+    /// Show or hide the hardware cursor. It is also hidden while scrolled up, regardless of this.
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        self.cursor_visible = visible;
+        self.update_cursor_shape();
+    }
+
+    /// The colors characters are written in
+    #[must_use]
+    pub fn color_code(&self) -> ColorCode {
+        self.color_code
+    }
+
+    /// Write in `foreground` on `background` until the matching [`Writer::pop_color`].
+    ///
+    /// Up to 16 colors are remembered, pops of any pushed past that leave the color as it is.
+    pub fn push_color(&mut self, foreground: Color, background: Color) {
+        self.push_style(Style::new(ColorCode::new(foreground, background)));
+    }
+
+    /// Write in `foreground` on the current background until the matching [`Writer::pop_color`]
+    pub fn push_foreground(&mut self, foreground: Color) {
+        self.push_style(Style {
+            foreground: foreground as u8,
+            bold: false,
+            ..self.style
+        });
+    }
+
+    /// Write in `background` with the current foreground until the matching [`Writer::pop_color`]
+    pub fn push_background(&mut self, background: Color) {
+        self.push_style(Style {
+            background: background as u8,
+            ..self.style
+        });
+    }
+
+    /// Go back to the colors from before the last push, does nothing if nothing is pushed
+    pub fn pop_color(&mut self) {
+        if self.color_depth == 0 {
+            return;
+        }
+
+        self.color_depth -= 1;
+        if let Some(&style) = self.color_stack.get(self.color_depth) {
+            self.style = style;
+            self.color_code = style.color_code();
+        }
+    }
+
+    fn push_style(&mut self, style: Style) {
+        if let Some(saved) = self.color_stack.get_mut(self.color_depth) {
+            *saved = self.style;
+        }
+        self.color_depth = self.color_depth.saturating_add(1);
+
+        self.style = style;
+        self.color_code = style.color_code();
+    }
+
+    /// Write `s` starting at `row` and `column`, without moving the cursor.
+    ///
+    /// Newlines are not handled and `s` is cut off at the end of the row, so nothing scrolls.
+    pub fn write_at(&mut self, row: usize, column: usize, s: &str) {
+        if row >= BUFFER_HEIGHT {
+            return;
+        }
+
+        self.scroll_to_bottom();
+        for (col, c) in (column..BUFFER_WIDTH).zip(s.chars()) {
+            let char = Char {
+                ascii_char: glyph(c),
+                color_code: self.color_code,
+            };
+            self.write_char(row, col, char);
+        }
+    }
+
+    /// Blank the whole screen and go back to the start of the bottom row.
+    ///
+    /// The scrollback history is kept.
+    pub fn clear(&mut self) {
+        self.scroll_to_bottom();
+        for row in 0..BUFFER_HEIGHT {
+            self.clear_row(row);
+        }
+        self.set_cursor(BUFFER_HEIGHT - 1, 0);
+    }
+
+    /// Show `rows` older rows of the scrollback history, as far as there is history
+    pub fn scroll_up(&mut self, rows: usize) {
+        let scroll = self.scroll.saturating_add(rows).min(self.history.len());
+        if scroll != self.scroll {
+            self.scroll = scroll;
+            self.redraw();
+            self.update_cursor_shape();
+        }
+    }
+
+    /// Show `rows` newer rows, towards what is currently being written
+    pub fn scroll_down(&mut self, rows: usize) {
+        let scroll = self.scroll.saturating_sub(rows);
+        if scroll != self.scroll {
+            self.scroll = scroll;
+            self.redraw();
+            self.update_cursor_shape();
+        }
+    }
+
+    /// Go back to showing what is currently being written, which any new output does too
+    pub fn scroll_to_bottom(&mut self) {
+        self.scroll_down(self.scroll);
+    }
+
+    /// How many rows the view is scrolled up from the bottom
+    #[must_use]
+    pub fn scroll_offset(&self) -> usize {
+        self.scroll
+    }
+
+    /// Swap in `history` to keep up to `rows` rows that scrolled off the screen,
+    /// moving over as many of the newest rows as fit. Returns the old history.
+    ///
+    /// `history` must already be able to hold `rows` rows, so that the writer never allocates:
+    /// the heap logs when it grows, which would need the writer.
+    fn replace_history(&mut self, mut history: VecDeque<Row>, rows: usize) -> VecDeque<Row> {
+        let skip = self.history.len().saturating_sub(rows);
+        history.extend(self.history.drain(skip..));
+
+        self.scrollback = rows;
+        self.scroll = self.scroll.min(history.len());
+        let old = core::mem::replace(&mut self.history, history);
+        self.redraw();
+        self.update_cursor_shape();
+        old
+    }
+
+    fn new_line(&mut self) {
+        self.column_pos = 0;
+        if self.row_pos < BUFFER_HEIGHT - 1 {
+            self.row_pos += 1;
+            return;
+        }
+
+        if self.scrollback > 0 {
+            if self.history.len() == self.scrollback {
+                self.history.pop_front();
+            }
+            self.history.push_back(self.screen[0]);
+        }
+
+        self.screen.copy_within(1.., 0);
+        self.clear_row(BUFFER_HEIGHT - 1);
+        self.redraw();
+    }
+
+    fn clear_row(&mut self, row: usize) {
+        self.erase(row, 0..BUFFER_WIDTH);
+    }
+
+    /// Write to the screen, and to the vga buffer if it is showing the screen
+    fn write_char(&mut self, row: usize, col: usize, char: Char) {
+        self.screen[row][col] = char;
+        if self.active && self.scroll == 0 {
+            write_vga(row, col, char);
+        }
+    }
+
+    /// Make this the console that is shown, copying it to the vga buffer
+    fn show(&mut self) {
+        self.active = true;
+        self.redraw();
+        self.update_cursor_shape();
+        self.update_cursor();
+    }
+
+    fn apply(&mut self, action: Action) {
+        let (row, col) = (self.row_pos, self.column_pos);
+        let last_row = BUFFER_HEIGHT - 1;
+        let last_col = BUFFER_WIDTH - 1;
+
+        match action {
+            Action::Print(byte) => self.put_glyph(byte),
+            Action::LineFeed => self.new_line(),
+            Action::CarriageReturn => self.column_pos = 0,
+            Action::Tab => self.column_pos = ((col / TAB_WIDTH + 1) * TAB_WIDTH).min(last_col),
+            Action::Backspace => self.column_pos = col.saturating_sub(1),
+            Action::CursorUp(n) => self.row_pos = row.saturating_sub(n),
+            Action::CursorDown(n) => self.row_pos = row.saturating_add(n).min(last_row),
+            Action::CursorForward(n) => self.column_pos = col.saturating_add(n).min(last_col),
+            Action::CursorBack(n) => self.column_pos = col.saturating_sub(n),
+            Action::NextLine(n) => {
+                self.row_pos = row.saturating_add(n).min(last_row);
+                self.column_pos = 0;
+            }
+            Action::PreviousLine(n) => {
+                self.row_pos = row.saturating_sub(n);
+                self.column_pos = 0;
+            }
+            Action::CursorColumn(column) => self.column_pos = column.min(last_col),
+            Action::CursorPosition { row, column } => {
+                self.row_pos = row.min(last_row);
+                self.column_pos = column.min(last_col);
+            }
+            Action::EraseDisplay(erase) => self.erase_display(erase),
+            Action::EraseLine(erase) => self.erase_line(erase),
+            Action::SaveCursor => self.saved_cursor = (row, col),
+            Action::RestoreCursor => (self.row_pos, self.column_pos) = self.saved_cursor,
+            Action::ShowCursor(visible) => self.set_cursor_visible(visible),
+            Action::Sgr(sgr) => self.apply_sgr(sgr),
+        }
+    }
+
+    fn apply_sgr(&mut self, sgr: Sgr) {
+        let ansi_color = |color: u8| ANSI_COLORS[usize::from(color & 0xf)] as u8;
+
+        match sgr {
+            Sgr::Reset => self.style = Style::new(self.default_color),
+            Sgr::Bold(bold) => self.style.bold = bold,
+            Sgr::Reverse(reverse) => self.style.reverse = reverse,
+            Sgr::Foreground(color) => self.style.foreground = ansi_color(color),
+            Sgr::Background(color) => self.style.background = ansi_color(color),
+            Sgr::DefaultForeground => {
+                self.style.foreground = Style::new(self.default_color).foreground;
+            }
+            Sgr::DefaultBackground => {
+                self.style.background = Style::new(self.default_color).background;
+            }
+        }
+        self.color_code = self.style.color_code();
+    }
+
+    fn erase_display(&mut self, erase: Erase) {
+        let row = self.row_pos;
+        let rows = match erase {
+            Erase::ToEnd => row + 1..BUFFER_HEIGHT,
+            Erase::ToStart => 0..row,
+            Erase::All => 0..BUFFER_HEIGHT,
+            Erase::Scrollback => {
+                self.history.clear();
+                return;
+            }
+        };
+
+        if matches!(erase, Erase::ToEnd | Erase::ToStart) {
+            self.erase_line(erase);
+        }
+        for row in rows {
+            self.clear_row(row);
+        }
+    }
+
+    /// Erase part of the cursor's row
+    fn erase_line(&mut self, erase: Erase) {
+        let col = self.column_pos;
+        let columns = match erase {
+            Erase::ToEnd => col..BUFFER_WIDTH,
+            Erase::ToStart => 0..(col + 1).min(BUFFER_WIDTH),
+            Erase::All | Erase::Scrollback => 0..BUFFER_WIDTH,
+        };
+        self.erase(self.row_pos, columns);
+    }
+
+    fn erase(&mut self, row: usize, columns: Range<usize>) {
+        let blank = Char {
+            ascii_char: b' ',
+            color_code: self.color_code,
+        };
+        for col in columns {
+            self.write_char(row, col, blank);
+        }
+    }
+
+    /// Move the hardware cursor to where the next character will be written
+    fn update_cursor(&self) {
+        if !self.active {
+            return;
+        }
+
+        // at the end of a row, the next character goes at the start of the next one
+        let col = self.column_pos.min(BUFFER_WIDTH - 1);
+        #[allow(
+            clippy::cast_possible_truncation,
+            reason = "the screen has 2000 characters"
+        )]
+        let index = (self.row_pos * BUFFER_WIDTH + col) as u16;
+        cursor::set_position(index);
+    }
+
+    fn update_cursor_shape(&self) {
+        if !self.active {
+            return;
+        }
+        cursor::set_shape(self.cursor_shape, self.cursor_visible && self.scroll == 0);
+    }
+
+    /// Copy the rows that should be visible to the vga buffer
+    fn redraw(&mut self) {
+        if !self.active {
+            return;
+        }
+
+        // index of the top visible row, counting the history and then the screen
+        let top = self.history.len() - self.scroll;
+        for row in 0..BUFFER_HEIGHT {
+            let index = top + row;
+            let source = match self.history.get(index) {
+                Some(source) => source,
+                None => &self.screen[index - self.history.len()],
+            };
+            for (col, &char) in source.iter().enumerate() {
+                write_vga(row, col, char);
+            }
+        }
+    }
+}
+
+/// The byte that shows `c`
+fn glyph(c: char) -> u8 {
+    cp437::from_char(c).unwrap_or(cp437::REPLACEMENT)
+}
+
+/// Keep up to `rows` rows that scrolled off the top of the screen, so they can be scrolled back to.
+///
+/// The history is kept in the heap, so this must not be called before the heap is initialised.
+pub fn set_scrollback(rows: usize) {
+    for console in CONSOLES.iter() {
+        // allocate without holding the writer, since growing the heap logs
+        let history = VecDeque::with_capacity(rows);
+        let old = x86_64::instructions::interrupts::without_interrupts(|| {
+            console.lock().replace_history(history, rows)
+        });
+        // freed only once the writer is unlocked too
+        drop(old);
+    }
+}
+
+/// Scroll the active console's view up by `rows`, see [`Writer::scroll_up`]
+pub fn scroll_up(rows: usize) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        CONSOLES[active_console()].lock().scroll_up(rows);
+    });
+}
+
+/// Scroll the active console's view down by `rows`, see [`Writer::scroll_down`]
+pub fn scroll_down(rows: usize) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        CONSOLES[active_console()].lock().scroll_down(rows);
+    });
+}
+
+/// Index of the console in [`CONSOLES`] that is shown
+#[must_use]
+pub fn active_console() -> usize {
+    ACTIVE_CONSOLE.load(Ordering::Relaxed)
+}
+
+/// Show the console at `index` in [`CONSOLES`] instead of the active one, does nothing if there is no such console
+pub fn switch_console(index: usize) {
+    if index >= CONSOLE_COUNT {
+        return;
+    }
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let active = active_console();
+        if index == active {
+            return;
+        }
+
+        CONSOLES[active].lock().active = false;
+        CONSOLES[index].lock().show();
+        ACTIVE_CONSOLE.store(index, Ordering::Relaxed);
+    });
+}
+
+#[test_case]
+fn test_println() {
+    println!("test output");
+}
+
+#[test_case]
+fn test_println_many() {
+    for _ in 0..200 {
+        println!("hi");
+    }
+}
+
+#[test_case]
+fn test_set_column() {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        writeln!(writer).expect("writeln failed");
+        writer.write_str("abc");
+        writer.set_column(1);
+        writer.write_str("x");
+
+        assert_eq!(writer.column(), 2);
+        let row = read_vga_row(BUFFER_HEIGHT - 1);
+        assert_eq!(row[0].ascii_char, b'a');
+        assert_eq!(row[1].ascii_char, b'x');
+        assert_eq!(row[2].ascii_char, b'c');
+    });
+}
+
+#[test_case]
+fn test_println_output() {
+    let test = "Hello, World!";
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        writeln!(writer, "\n{test}").expect("writeln failed");
+
+        for (i, c) in test.chars().enumerate() {
+            let screen_char = read_vga(BUFFER_HEIGHT - 2, i);
+
+            assert_eq!(char::from(screen_char.ascii_char), c);
+        }
+    });
+}
+
+#[test_case]
+fn test_scrollback() {
+    set_scrollback(DEFAULT_SCROLLBACK);
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        writeln!(writer, "\nfirst").expect("writeln failed");
+        for _ in 0..BUFFER_HEIGHT - 1 {
+            writeln!(writer).expect("writeln failed");
+        }
+        // `first` just scrolled off the top
+        assert_eq!(read_vga(0, 0).ascii_char, b' ');
+
+        writer.scroll_up(1);
+        assert_eq!(writer.scroll_offset(), 1);
+        assert_eq!(read_vga(0, 0).ascii_char, b'f');
+        writer.scroll_up(usize::MAX);
+        assert_eq!(writer.scroll_offset(), writer.history.len());
+
+        // new output snaps back to the bottom
+        writer.write_str("x");
+        assert_eq!(writer.scroll_offset(), 0);
+        assert_eq!(read_vga(0, 0).ascii_char, b' ');
+        assert_eq!(read_vga(BUFFER_HEIGHT - 1, 0).ascii_char, b'x');
+    });
+}
+
+#[test_case]
+fn test_set_cursor() {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        writer.set_cursor(2, BUFFER_WIDTH - 1);
+        writer.write_str("ab\nc");
+        assert_eq!(writer.cursor_position(), (4, 1));
+
+        // wraps onto the next row, without scrolling
+        assert_eq!(read_vga(2, BUFFER_WIDTH - 1).ascii_char, b'a');
+        assert_eq!(read_vga(3, 0).ascii_char, b'b');
+        assert_eq!(read_vga(4, 0).ascii_char, b'c');
+
+        writer.write_at(0, BUFFER_WIDTH - 2, "xyz");
+        assert_eq!(read_vga(0, BUFFER_WIDTH - 1).ascii_char, b'y');
+        assert_eq!(writer.cursor_position(), (4, 1));
+
+        // positions are clamped to the screen
+        writer.set_cursor(usize::MAX, usize::MAX);
+        assert_eq!(writer.cursor_position(), (BUFFER_HEIGHT - 1, BUFFER_WIDTH));
+        writer.set_cursor(BUFFER_HEIGHT - 1, 0);
+    });
+}
+
+#[test_case]
+fn test_escape_sequences() {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        writeln!(writer).expect("writeln failed");
+        write!(writer, "abcdef\r\x1b[2Cx\x1b[K\x1b[31;1my\x1b[0m\tz").expect("write failed");
+
+        let row = read_vga_row(BUFFER_HEIGHT - 1);
+        assert_eq!(row[2].ascii_char, b'x');
+        // erased to the end of the row
+        assert_eq!(row[4].ascii_char, b' ');
+        assert_eq!(row[3].ascii_char, b'y');
+        assert_eq!(
+            row[3].color_code,
+            ColorCode::new(Color::LightRed, Color::Black)
+        );
+        assert_eq!(row[8].ascii_char, b'z');
+        assert_eq!(row[8].color_code, writer.default_color);
+    });
+}
+
+#[test_case]
+fn test_write_unicode() {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        writeln!(writer).expect("writeln failed");
+        writer.write_str("é€┐◙");
+
+        // one glyph per character, even if it has no glyph, and ◙ is not a new line even though its glyph is `\n`
+        assert_eq!(writer.cursor_position(), (BUFFER_HEIGHT - 1, 4));
+        let row = read_vga_row(BUFFER_HEIGHT - 1);
+        assert_eq!(row[0].ascii_char, 0x82);
+        assert_eq!(row[1].ascii_char, cp437::REPLACEMENT);
+        assert_eq!(row[2].ascii_char, 0xbf);
+        assert_eq!(row[3].ascii_char, b'\n');
+    });
+}
+
+#[test_case]
+fn test_switch_console() {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        writer.clear();
+        writeln!(writer, "\nfirst").expect("writeln failed");
+        // only written to the second console's own screen
+        write!(CONSOLES[1].lock(), "\x1b[2J\x1b[Hsecond").expect("write failed");
+        assert_eq!(read_vga(0, 0).ascii_char, b' ');
+    });
+
+    switch_console(1);
+    assert_eq!(active_console(), 1);
+    assert_eq!(read_vga(0, 0).ascii_char, b's');
+
+    switch_console(0);
+    assert_eq!(active_console(), 0);
+    assert_eq!(read_vga(BUFFER_HEIGHT - 2, 0).ascii_char, b'f');
+
+    switch_console(CONSOLE_COUNT);
+    assert_eq!(active_console(), 0);
+}
+
+#[test_case]
+fn test_color_stack() {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        let default = writer.color_code();
+
+        writer.push_color(Color::Yellow, Color::Blue);
+        writer.push_foreground(Color::Red);
+        assert_eq!(writer.color_code(), ColorCode::new(Color::Red, Color::Blue));
+        writer.pop_color();
+        assert_eq!(
+            writer.color_code(),
+            ColorCode::new(Color::Yellow, Color::Blue)
+        );
+        writer.pop_color();
+        assert_eq!(writer.color_code(), default);
+
+        // nothing left to pop
+        writer.pop_color();
+        assert_eq!(writer.color_code(), default);
+
+        // pushes past what is remembered still pop back to the start
+        for _ in 0..COLOR_STACK_SIZE + 2 {
+            writer.push_foreground(Color::White);
+        }
+        for _ in 0..COLOR_STACK_SIZE + 2 {
+            writer.pop_color();
+        }
+        assert_eq!(writer.color_code(), default);
+    });
+}
+
+#[test_case]
+fn test_print_colored() {
+    let default =
+        x86_64::instructions::interrupts::without_interrupts(|| WRITER.lock().color_code());
+
+    {
+        let _guard = push_color(Color::White, Color::Magenta);
+        println!();
+        print_colored!(Color::Cyan, "a{}", 1);
+        print!("b");
+    }
+    println_colored!(Color::Pink, Color::Black, "c");
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let writer = WRITER.lock();
+        assert_eq!(writer.color_code(), default);
+
+        let row = read_vga_row(BUFFER_HEIGHT - 2);
+        assert_eq!(
+            row[0].color_code,
+            ColorCode::new(Color::Cyan, Color::Magenta)
+        );
+        assert_eq!(
+            row[2].color_code,
+            ColorCode::new(Color::White, Color::Magenta)
+        );
+        assert_eq!(row[3].color_code, ColorCode::new(Color::Pink, Color::Black));
+    });
+}
+
+#[test_case]
+fn test_logger() {
+    let logger = Logger::new(LevelFilter::Trace);
+    logger.log(
+        &log::Record::builder()
+            .args(format_args!("answer {}", 42))
+            .level(Level::Warn)
+            .module_path(Some("osos::test"))
+            .line(Some(7))
+            .build(),
+    );
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let row = read_vga_row(BUFFER_HEIGHT - 2);
+        let expected = "osos::test:7->WARN: answer 42";
+        for (i, c) in expected.bytes().enumerate() {
+            assert_eq!(row[i].ascii_char, c);
+            assert_eq!(
+                row[i].color_code,
+                ColorCode::new(Color::Yellow, Color::Black)
+            );
+        }
+    });
+}
@@ -0,0 +1,118 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(osos::runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use osos::{
+    gdt,
+    memory::{
+        self,
+        address_space::{AddressSpace, USER_START},
+        allocator,
+    },
+    syscall, thread,
+};
+use x86_64::{structures::paging::PageTableFlags, VirtAddr};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    osos::init();
+    unsafe { memory::init(boot_info) };
+    gdt::init_stacks().expect("interrupt stack initialization failed");
+    allocator::init_heap().expect("heap initialization failed");
+    thread::init().expect("scheduler initialization failed");
+
+    test_main();
+    loop {}
+}
+
+fn used_frames() -> usize {
+    memory::with_frame_allocator(|frame_allocator| frame_allocator.used_frames())
+}
+
+#[test_case]
+fn spaces_are_independent() {
+    let start = VirtAddr::new(USER_START);
+    let end = start + 4096u64;
+
+    let mut first = AddressSpace::new().expect("failed to create address space");
+    let mut second = AddressSpace::new().expect("failed to create address space");
+    first
+        .map(start, end, PageTableFlags::WRITABLE)
+        .expect("failed to map");
+    second
+        .map(start, end, PageTableFlags::WRITABLE)
+        .expect("failed to map");
+
+    first.write(start, b"first").expect("failed to write");
+    second.write(start, b"second").expect("failed to write");
+
+    let (first_phys, flags) = first.translate(start).expect("page not mapped");
+    let (second_phys, _) = second.translate(start).expect("page not mapped");
+    assert_ne!(first_phys, second_phys);
+    assert!(flags.contains(PageTableFlags::USER_ACCESSIBLE | PageTableFlags::WRITABLE));
+
+    // the kernel's address space does not see either
+    assert!(memory::vm::translate(start).is_none());
+}
+
+#[test_case]
+fn drop_frees_frames() {
+    let before = used_frames();
+    {
+        let start = VirtAddr::new(USER_START);
+        let mut space = AddressSpace::new().expect("failed to create address space");
+        space
+            .map(start, start + 16 * 4096u64, PageTableFlags::WRITABLE)
+            .expect("failed to map");
+        assert!(used_frames() > before + 16);
+    }
+    assert_eq!(used_frames(), before);
+}
+
+#[test_case]
+fn rejects_kernel_addresses() {
+    let mut space = AddressSpace::new().expect("failed to create address space");
+    let start = VirtAddr::new(0x4444_4444_0000);
+    assert!(space
+        .map(start, start + 4096u64, PageTableFlags::WRITABLE)
+        .is_err());
+}
+
+/// exit(0)
+const EXIT: &[u8] = &[
+    0xb8, 0x01, 0x00, 0x00, 0x00, // mov eax, 1
+    0x31, 0xff, // xor edi, edi
+    0x0f, 0x05, // syscall
+];
+
+#[test_case]
+fn runs_user_code_in_space() {
+    let start = VirtAddr::new(USER_START);
+    // one page of code, one page of stack
+    let end = start + 2 * 4096u64;
+
+    let mut space = AddressSpace::new().expect("failed to create address space");
+    space
+        .map(start, end, PageTableFlags::WRITABLE)
+        .expect("failed to map");
+    space.write(start, EXIT).expect("failed to write");
+
+    let handle = thread::spawn_thread_in(Arc::new(space), move || unsafe {
+        syscall::enter_user_mode(start, end)
+    })
+    .expect("failed to spawn thread");
+    assert!(handle.join().is_none());
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    osos::test_panic_handler(info)
+}
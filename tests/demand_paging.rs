@@ -0,0 +1,151 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(osos::runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use osos::memory::{
+    self, allocator,
+    stack::{KernelStack, DEFAULT_STACK_SIZE},
+    vm::{self, RegionKind, VmError},
+};
+use x86_64::{structures::paging::PageTableFlags, VirtAddr};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    osos::init();
+    unsafe { memory::init(boot_info) };
+    allocator::init_heap().expect("heap initialization failed");
+
+    test_main();
+    loop {}
+}
+
+const REGION_START: u64 = 0x5555_0000_0000;
+const REGION_PAGES: u64 = 16;
+
+fn used_frames() -> usize {
+    memory::with_mapper(|_, frame_allocator| frame_allocator.used_frames())
+}
+
+#[test_case]
+fn maps_on_first_access() {
+    let start = VirtAddr::new(REGION_START);
+    let end = start + REGION_PAGES * 4096;
+    let flags = PageTableFlags::WRITABLE;
+    vm::reserve(start, end, flags, RegionKind::Other, true).expect("failed to reserve region");
+
+    let before = used_frames();
+
+    let ptr: *mut u64 = (start + 3 * 4096u64).as_mut_ptr();
+    unsafe {
+        // fresh pages are zeroed
+        assert_eq!(ptr.read_volatile(), 0);
+        ptr.write_volatile(0xdead_beef);
+        assert_eq!(ptr.read_volatile(), 0xdead_beef);
+    }
+
+    // only the touched page (and maybe its page tables) got a frame
+    let used = used_frames() - before;
+    assert!((1..=4).contains(&used));
+
+    // releasing frees the page's frame, but not the page tables
+    vm::release(start).expect("failed to release region");
+    assert!(used_frames() - before < used);
+}
+
+#[test_case]
+fn rejects_overlapping_regions() {
+    let start = VirtAddr::new(REGION_START + 0x10_0000);
+    let end = start + REGION_PAGES * 4096;
+    let flags = PageTableFlags::WRITABLE;
+
+    vm::reserve(start, end, flags, RegionKind::Other, true).expect("failed to reserve region");
+    assert!(matches!(
+        vm::reserve(
+            start + 4096u64,
+            end + 4096u64,
+            flags,
+            RegionKind::Other,
+            true
+        ),
+        Err(VmError::Overlaps)
+    ));
+
+    vm::release(start).expect("failed to release region");
+}
+
+#[test_case]
+fn map_and_unmap_range() {
+    let region = vm::reserve_anywhere(
+        REGION_PAGES * 4096,
+        PageTableFlags::WRITABLE,
+        RegionKind::Other,
+        false,
+    )
+    .expect("failed to reserve region");
+
+    let before = used_frames();
+    vm::map_range(region.start, region.end).expect("failed to map region");
+    assert!(vm::translate(region.start).is_some());
+
+    let ptr: *mut u64 = (region.end - 8u64).as_mut_ptr();
+    unsafe {
+        ptr.write_volatile(42);
+        assert_eq!(ptr.read_volatile(), 42);
+    }
+
+    vm::unmap_range(region.start, region.end).expect("failed to unmap region");
+    assert!(vm::translate(region.start).is_none());
+    // page tables stay around, but every page's frame is freed again
+    assert!(used_frames() - before <= 3);
+
+    vm::release(region.start).expect("failed to release region");
+}
+
+#[test_case]
+fn protect_splits_region() {
+    let region = vm::reserve_anywhere(4 * 4096, PageTableFlags::WRITABLE, RegionKind::Other, false)
+        .expect("failed to reserve region");
+    let middle = region.start + 4096u64;
+
+    vm::protect(middle, middle + 4096u64, PageTableFlags::empty()).expect("failed to protect");
+
+    let protected = vm::region_at(middle).expect("region disappeared");
+    assert_eq!((protected.start, protected.end), (middle, middle + 4096u64));
+    assert!(!protected.flags.contains(PageTableFlags::WRITABLE));
+
+    let before = vm::region_at(region.start).expect("region disappeared");
+    assert_eq!(before.end, middle);
+    assert!(before.flags.contains(PageTableFlags::WRITABLE));
+
+    for start in [region.start, middle, middle + 4096u64] {
+        vm::release(start).expect("failed to release region");
+    }
+}
+
+#[test_case]
+fn kernel_stack_has_guard_page() {
+    let stack = KernelStack::new(DEFAULT_STACK_SIZE).expect("failed to allocate stack");
+    let guard = stack.guard();
+
+    assert_eq!(guard.kind, RegionKind::Guard);
+    assert_eq!(guard.end, stack.bottom());
+    assert_eq!(stack.top() - stack.bottom(), DEFAULT_STACK_SIZE);
+
+    assert!(vm::translate(guard.start).is_none());
+    assert!(vm::translate(stack.bottom()).is_some());
+    assert!(vm::translate(stack.top() - 1u64).is_some());
+
+    let bottom = stack.bottom();
+    drop(stack);
+    assert!(vm::region_at(bottom).is_none());
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    osos::test_panic_handler(info)
+}
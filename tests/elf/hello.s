@@ -0,0 +1,25 @@
+# user program for tests/elf_loader.rs, build with:
+#   as hello.s -o hello.o
+#   ld -static -nostdlib -z max-page-size=4096 -z noexecstack -Ttext-segment=0x200000000000 hello.o -o hello.elf
+#   strip hello.elf
+.section .text
+.globl _start
+_start:
+    # write(1, msg, msg_len)
+    mov $0, %eax
+    mov $1, %edi
+    lea msg(%rip), %rsi
+    mov $msg_len, %edx
+    syscall
+
+    # exit(argc)
+    mov (%rsp), %rdi
+    mov $1, %eax
+    syscall
+
+.section .data
+msg: .ascii "hello from elf\n"
+msg_len = . - msg
+
+.section .bss
+buffer: .skip 8192
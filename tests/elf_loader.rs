@@ -0,0 +1,175 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(osos::runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use osos::{
+    elf::{self, Elf, ElfError, LoadError, PT_LOAD},
+    gdt,
+    memory::{self, address_space::AddressSpace, allocator},
+    thread,
+};
+use x86_64::{structures::paging::PageTableFlags, VirtAddr};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    osos::init();
+    unsafe { memory::init(boot_info) };
+    gdt::init_stacks().expect("interrupt stack initialization failed");
+    allocator::init_heap().expect("heap initialization failed");
+    thread::init().expect("scheduler initialization failed");
+
+    test_main();
+    loop {}
+}
+
+/// Writes "hello from elf" and exits with argc, see `elf/hello.s`
+static HELLO: &[u8] = include_bytes!("elf/hello.elf");
+
+const TEXT: u64 = 0x2000_0000_1000;
+const DATA: u64 = 0x2000_0000_2000;
+/// The end of the data segment, including its bss
+const DATA_END: u64 = 0x2000_0000_4010;
+
+fn read_u64(space: &AddressSpace, addr: u64) -> u64 {
+    let mut buf = [0u8; 8];
+    space
+        .read(VirtAddr::new(addr), &mut buf)
+        .expect("failed to read");
+    u64::from_le_bytes(buf)
+}
+
+#[test_case]
+fn parses_headers() {
+    let elf = Elf::parse(HELLO).expect("failed to parse");
+    assert_eq!(elf.entry(), TEXT);
+    assert_eq!(
+        elf.program_headers().filter(|h| h.kind == PT_LOAD).count(),
+        3
+    );
+}
+
+#[test_case]
+fn rejects_invalid_headers() {
+    assert_eq!(Elf::parse(&HELLO[..32]).err(), Some(ElfError::TooShort));
+
+    let mut data = Vec::from(HELLO);
+    data[1] = b'X';
+    assert_eq!(Elf::parse(&data).err(), Some(ElfError::BadMagic));
+
+    let mut data = Vec::from(HELLO);
+    data[4] = 1;
+    assert_eq!(Elf::parse(&data).err(), Some(ElfError::Not64Bit));
+
+    // EM_AARCH64
+    let mut data = Vec::from(HELLO);
+    data[18] = 0xb7;
+    assert_eq!(Elf::parse(&data).err(), Some(ElfError::NotX86_64));
+
+    // the segments are cut off
+    assert_eq!(
+        Elf::parse(&HELLO[..0x1000]).err(),
+        Some(ElfError::BadSegment)
+    );
+}
+
+#[test_case]
+fn maps_segments_with_their_permissions() {
+    let elf = Elf::parse(HELLO).expect("failed to parse");
+    let mut space = AddressSpace::new().expect("failed to create address space");
+    let entry = elf::load(&elf, &mut space).expect("failed to load");
+    assert_eq!(entry.as_u64(), TEXT);
+
+    let (_, text) = space
+        .translate(VirtAddr::new(TEXT))
+        .expect("text not mapped");
+    assert!(!text.contains(PageTableFlags::WRITABLE));
+    assert!(!text.contains(PageTableFlags::NO_EXECUTE));
+
+    let (_, data) = space
+        .translate(VirtAddr::new(DATA))
+        .expect("data not mapped");
+    assert!(data.contains(PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE));
+
+    assert!(space.translate(VirtAddr::new(DATA_END - 1)).is_some());
+}
+
+#[test_case]
+fn zeroes_bss() {
+    let elf = Elf::parse(HELLO).expect("failed to parse");
+    let mut space = AddressSpace::new().expect("failed to create address space");
+    elf::load(&elf, &mut space).expect("failed to load");
+
+    let mut message = [0u8; 15];
+    space
+        .read(VirtAddr::new(DATA), &mut message)
+        .expect("failed to read");
+    assert_eq!(&message, b"hello from elf\n");
+
+    let mut bss = [0xffu8; 0x2001];
+    space
+        .read(VirtAddr::new(DATA + 0xf), &mut bss)
+        .expect("failed to read");
+    assert!(bss.iter().all(|&b| b == 0));
+}
+
+#[test_case]
+fn sets_up_arguments_on_stack() {
+    let elf = Elf::parse(HELLO).expect("failed to parse");
+    let mut space = AddressSpace::new().expect("failed to create address space");
+    let rsp = elf::setup_stack(&mut space, &elf, &["hello", "world"], &["TERM=vga"])
+        .expect("failed to set up stack")
+        .as_u64();
+    assert_eq!(rsp % 16, 0);
+    assert!(rsp < elf::USER_STACK_TOP);
+
+    assert_eq!(read_u64(&space, rsp), 2);
+    let arg = read_u64(&space, rsp + 16);
+    let mut buf = [0u8; 6];
+    space
+        .read(VirtAddr::new(arg), &mut buf)
+        .expect("failed to read");
+    assert_eq!(&buf, b"world\0");
+    assert_eq!(read_u64(&space, rsp + 24), 0);
+
+    let env = read_u64(&space, rsp + 32);
+    let mut buf = [0u8; 9];
+    space
+        .read(VirtAddr::new(env), &mut buf)
+        .expect("failed to read");
+    assert_eq!(&buf, b"TERM=vga\0");
+    assert_eq!(read_u64(&space, rsp + 40), 0);
+
+    // the first auxiliary vector entry is AT_PHDR
+    assert_eq!(read_u64(&space, rsp + 48), 3);
+}
+
+#[test_case]
+fn rejects_huge_arguments() {
+    let elf = Elf::parse(HELLO).expect("failed to parse");
+    let mut space = AddressSpace::new().expect("failed to create address space");
+    let huge = "a".repeat(elf::USER_STACK_SIZE as usize);
+    assert!(matches!(
+        elf::setup_stack(&mut space, &elf, &[&huge], &[]),
+        Err(LoadError::ArgumentsTooLong)
+    ));
+}
+
+#[test_case]
+fn runs_program() {
+    let handle = elf::exec(HELLO, &["hello"], &[]).expect("failed to exec");
+    // the program exits through the exit syscall, so the thread has no result
+    assert!(handle.join().is_none());
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    osos::test_panic_handler(info)
+}
@@ -12,15 +12,11 @@ use core::panic::PanicInfo;
 entry_point!(main);
 
 fn main(boot_info: &'static BootInfo) -> ! {
-    use osos::memory::allocator;
-    use osos::memory::paging::{self, BootInfoFrameAllocator};
-    use x86_64::VirtAddr;
+    use osos::memory::{self, allocator};
 
     osos::init();
-    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
-    let mut mapper = unsafe { paging::init_offset_table(phys_mem_offset) };
-    let mut frame_allocator = unsafe { BootInfoFrameAllocator::new(&boot_info.memory_map) };
-    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    unsafe { memory::init(boot_info) };
+    allocator::init_heap().expect("heap initialization failed");
 
     test_main();
     loop {}
@@ -48,7 +44,7 @@ fn large_vec() {
     assert_eq!(vec.iter().sum::<u64>(), (n - 1) * n / 2);
 }
 
-use osos::memory::allocator::HEAP_SIZE;
+use osos::memory::allocator::{HEAP_MAX_SIZE, HEAP_SIZE};
 
 #[test_case]
 fn many_boxes() {
@@ -58,6 +54,64 @@ fn many_boxes() {
     }
 }
 
+#[test_case]
+fn grows_past_initial_size() {
+    let size = HEAP_SIZE * 4;
+    assert!(size < HEAP_MAX_SIZE);
+
+    let mut vec = Vec::<u8>::with_capacity(size);
+    vec.resize(size, 0xaa);
+    assert!(vec.iter().all(|&b| b == 0xaa));
+}
+
+use osos::memory::allocator;
+
+#[test_case]
+fn stats_count_live_blocks() {
+    let before = allocator::heap_stats();
+    let value = Box::new(0u64);
+    let during = allocator::heap_stats();
+    drop(value);
+    let after = allocator::heap_stats();
+
+    // u64 goes in the 8 byte size class
+    assert_eq!(during.size_classes[0].live, before.size_classes[0].live + 1);
+    assert_eq!(after.size_classes[0].live, before.size_classes[0].live);
+    assert_eq!(
+        after.size_classes[0].cached,
+        during.size_classes[0].cached + 1
+    );
+    assert!(during.peak_allocated_bytes >= during.allocated_bytes);
+}
+
+#[test_case]
+fn no_leaks() {
+    let tracker = allocator::track_allocations();
+    {
+        let mut vec = Vec::new();
+        for i in 0..100 {
+            vec.push(Box::new(i));
+        }
+    }
+    let leaks = tracker.finish();
+    assert!(leaks.is_empty(), "{leaks:?}");
+}
+
+#[test_case]
+fn detects_leak() {
+    let tracker = allocator::track_allocations();
+    let leaked = Box::leak(Box::new([0u8; 100]));
+    let leaks = tracker.finish();
+
+    assert_eq!(leaks.leaked(), 1);
+    assert_eq!(leaks.leaked_bytes(), leaked.len());
+
+    let allocation = leaks.allocations().next().expect("leak not listed");
+    assert_eq!(allocation.addr, leaked.as_ptr() as usize);
+    // the allocator's own frames come first, so a real trace goes deeper than that
+    assert_ne!(allocation.backtrace[3], 0);
+}
+
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     osos::test_panic_handler(info)
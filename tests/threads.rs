@@ -0,0 +1,88 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(osos::runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::{
+    panic::PanicInfo,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+use osos::{
+    memory::{self, allocator},
+    thread,
+    time::{Duration, Instant},
+};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    osos::init();
+    unsafe { memory::init(boot_info) };
+    allocator::init_heap().expect("heap initialization failed");
+    thread::init().expect("scheduler initialization failed");
+
+    test_main();
+    loop {}
+}
+
+#[test_case]
+fn join_returns_result() {
+    let handle = thread::spawn_thread(|| 21 * 2).expect("failed to spawn thread");
+    assert_eq!(handle.join(), Some(42));
+}
+
+#[test_case]
+fn threads_run_concurrently() {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            thread::spawn_thread(|| {
+                for _ in 0..1000 {
+                    COUNTER.fetch_add(1, Ordering::Relaxed);
+                    thread::yield_now();
+                }
+            })
+            .expect("failed to spawn thread")
+        })
+        .collect();
+
+    for handle in handles {
+        handle.join();
+    }
+    assert_eq!(COUNTER.load(Ordering::Relaxed), 4000);
+}
+
+#[test_case]
+fn busy_thread_is_preempted() {
+    static STOP: AtomicBool = AtomicBool::new(false);
+
+    // never yields, so the main thread only runs again if it gets preempted
+    let busy = thread::spawn_thread(|| {
+        while !STOP.load(Ordering::Relaxed) {
+            core::hint::spin_loop();
+        }
+    })
+    .expect("failed to spawn thread");
+
+    thread::sleep(Duration::from_millis(50));
+    STOP.store(true, Ordering::Relaxed);
+    busy.join();
+}
+
+#[test_case]
+fn sleep_waits() {
+    let start = Instant::now();
+    thread::sleep(Duration::from_millis(20));
+    assert!(start.elapsed() >= Duration::from_millis(20));
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    osos::test_panic_handler(info)
+}
@@ -0,0 +1,87 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(osos::runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use osos::{
+    gdt,
+    memory::{
+        self,
+        address_space::USER_START,
+        allocator,
+        vm::{self, RegionKind},
+    },
+    syscall, thread,
+};
+use x86_64::{structures::paging::PageTableFlags, VirtAddr};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    osos::init();
+    unsafe { memory::init(boot_info) };
+    gdt::init_stacks().expect("interrupt stack initialization failed");
+    allocator::init_heap().expect("heap initialization failed");
+    thread::init().expect("scheduler initialization failed");
+
+    test_main();
+    loop {}
+}
+
+/// write(1, "hello\n", 6); exit(0)
+const HELLO: &[u8] = &[
+    0xb8, 0x00, 0x00, 0x00, 0x00, // mov eax, 0
+    0xbf, 0x01, 0x00, 0x00, 0x00, // mov edi, 1
+    0x48, 0x8d, 0x35, 0x10, 0x00, 0x00, 0x00, // lea rsi, [rip + 0x10]
+    0xba, 0x06, 0x00, 0x00, 0x00, // mov edx, 6
+    0x0f, 0x05, // syscall
+    0xb8, 0x01, 0x00, 0x00, 0x00, // mov eax, 1
+    0x31, 0xff, // xor edi, edi
+    0x0f, 0x05, // syscall
+    b'h', b'e', b'l', b'l', b'o', b'\n',
+];
+
+/// hlt, which user code is not allowed to run
+const PRIVILEGED: &[u8] = &[0xf4];
+
+/// Run `code` in ring 3 on a new thread and wait for it to exit
+fn run_user(code: &'static [u8]) {
+    let start = VirtAddr::new(USER_START);
+    // one page of code, one page of stack
+    let end = start + 2 * 4096u64;
+    let flags = PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+
+    vm::reserve(start, end, flags, RegionKind::Other, false).expect("failed to reserve");
+    vm::map_range(start, end).expect("failed to map");
+
+    unsafe {
+        start
+            .as_mut_ptr::<u8>()
+            .copy_from_nonoverlapping(code.as_ptr(), code.len());
+    }
+
+    let handle = thread::spawn_thread(move || unsafe { syscall::enter_user_mode(start, end) })
+        .expect("failed to spawn thread");
+    // user threads exit without returning
+    assert!(handle.join().is_none());
+
+    vm::release(start).expect("failed to release");
+}
+
+#[test_case]
+fn exit_syscall_ends_thread() {
+    run_user(HELLO);
+}
+
+#[test_case]
+fn privileged_instruction_kills_thread() {
+    run_user(PRIVILEGED);
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    osos::test_panic_handler(info)
+}